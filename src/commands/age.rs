@@ -6,7 +6,7 @@ use poise::{
 	CreateReply,
 };
 
-use crate::config::Context;
+use crate::{config::Context, helpers::ContextExt};
 
 /// Sends the timestamp of the ID or user's creation date.
 #[poise::command(
@@ -24,7 +24,7 @@ pub async fn age(
 		bool,
 	>,
 ) -> Result<()> {
-	let ephemeral = ephemeral.unwrap_or(false);
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(false));
 	if ephemeral {
 		ctx.defer_ephemeral().await?;
 	} else {
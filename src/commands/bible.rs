@@ -8,7 +8,7 @@ use poise::{
 };
 use regex::Regex;
 
-use crate::config::ApplicationContext;
+use crate::{config::ApplicationContext, helpers::ContextExt};
 
 const KJV_BIBLE: &str = include_str!("../../assets/kjv.txt");
 
@@ -104,7 +104,7 @@ pub async fn bible(
 	let ephemeral = match data.ephemeral.as_deref() {
 		Some("false") => false,
 		Some(_) => true,
-		None => false,
+		None => ctx.default_ephemeral(false),
 	};
 
 	let reply = CreateReply::default()
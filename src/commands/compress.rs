@@ -0,0 +1,265 @@
+use std::fs;
+
+use crate::{
+	config::{ApplicationContext, Context},
+	helpers::{
+		change_extension, format_size_change, AttachmentOrThumbnail,
+		ContextExt,
+	},
+	jobs::{self, JobClass},
+	media,
+	picker::MenuTarget,
+	probe::{probe, MediaInfo},
+	workspace::Workspace,
+};
+use anyhow::{anyhow, Result};
+use poise::{
+	serenity_prelude::{
+		Attachment, CreateAllowedMentions, CreateAttachment, Message, User,
+	},
+	ChoiceParameter, CreateReply, Modal,
+};
+use serde::{Deserialize, Serialize};
+
+/// Discord's upload limits. Discord calls them MB but counts in MiB.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ChoiceParameter)]
+enum UploadLimit {
+	#[name = "10 MB (Free)"]
+	Free,
+	#[name = "50 MB (Nitro Basic)"]
+	NitroBasic,
+	#[name = "500 MB (Nitro)"]
+	Nitro,
+	Custom,
+}
+impl UploadLimit {
+	fn megabytes(&self) -> Option<f64> {
+		match self {
+			Self::Free => Some(10.0),
+			Self::NitroBasic => Some(50.0),
+			Self::Nitro => Some(500.0),
+			Self::Custom => None,
+		}
+	}
+}
+
+#[derive(Debug, Modal)]
+#[name = "Compress"]
+struct CompressModal {
+	#[name = "Target Size"]
+	#[placeholder = "10, 50, 500 or any size in MB. (default: 10)"]
+	target_size: Option<String>,
+	#[placeholder = "Whether or not to show the message."]
+	ephemeral: Option<String>,
+}
+impl CompressModal {
+	/// The target typed into the modal, in bytes.
+	fn target_size(&self) -> Result<u64> {
+		let target_megabytes = match self.target_size.as_deref() {
+			Some(target_size) => target_size
+				.trim()
+				.trim_end_matches(['M', 'B', 'm', 'b'])
+				.trim()
+				.parse::<f64>()
+				.map_err(|_| {
+					anyhow!("`{}` isn't a size in MB.", target_size.trim())
+				})?,
+			None => UploadLimit::Free.megabytes().unwrap_or(10.0),
+		};
+		megabytes_to_bytes(target_megabytes)
+	}
+}
+
+/// Compresses an image, video or audio file to fit under an upload limit.
+#[poise::command(
+	context_menu_command = "Compress",
+	owners_only,
+	track_edits,
+	install_context = "User",
+	interaction_context = "Guild|BotDm|PrivateChannel",
+	ephemeral
+)]
+pub async fn compress_context_menu(
+	ctx: ApplicationContext<'_>,
+	#[description = "The message to compress."] message: Message,
+) -> Result<()> {
+	compress_menu(ctx, MenuTarget::Message(&message)).await
+}
+
+/// Compresses a user's avatar or banner to fit under an upload limit.
+#[poise::command(
+	context_menu_command = "Profile Compress",
+	owners_only,
+	track_edits,
+	install_context = "User",
+	interaction_context = "Guild|BotDm|PrivateChannel",
+	ephemeral
+)]
+pub async fn compress_user_context_menu(
+	ctx: ApplicationContext<'_>,
+	#[description = "The user with the avatar or banner."] user: User,
+) -> Result<()> {
+	compress_menu(ctx, MenuTarget::User(&user)).await
+}
+
+/// What both Compress menus do once they know where the file comes from.
+async fn compress_menu(
+	ctx: ApplicationContext<'_>,
+	target: MenuTarget<'_>,
+) -> Result<()> {
+	let data = CompressModal::execute(ctx)
+		.await?
+		.ok_or_else(|| anyhow!("No modal data."))?;
+
+	let ephemeral = match data.ephemeral.as_deref() {
+		Some("false") => false,
+		Some(_) => true,
+		None => ctx.default_ephemeral(false),
+	};
+
+	let target_size = data.target_size()?;
+
+	let attachment = &target.pick(ctx).await?;
+
+	let compressed = jobs::run(
+		ctx.into(),
+		JobClass::Video,
+		ephemeral,
+		compress_attachment(&ctx.data().http, attachment, target_size),
+	)
+	.await?;
+
+	ctx.send(compressed.reply().ephemeral(ephemeral)).await?;
+
+	Ok(())
+}
+
+/// Compresses an image, video or audio file to fit under an upload limit.
+#[poise::command(
+	slash_command,
+	owners_only,
+	track_edits,
+	install_context = "User",
+	interaction_context = "Guild|BotDm|PrivateChannel",
+	ephemeral
+)]
+pub async fn compress(
+	ctx: Context<'_>,
+	#[description = "The file to compress."] attachment: Attachment,
+	#[description = "The upload limit to fit under. (default: 10 MB)"]
+	target: Option<UploadLimit>,
+	#[description = "The size in MB to fit under when the target is Custom."]
+	#[min = 0.1]
+	custom_size: Option<f64>,
+	#[description = "Whether or not to show the message."] ephemeral: Option<
+		bool,
+	>,
+) -> Result<()> {
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(false));
+	if ephemeral {
+		ctx.defer_ephemeral().await?;
+	} else {
+		ctx.defer().await?;
+	}
+
+	let target_megabytes = match (target, custom_size) {
+		(Some(UploadLimit::Custom) | None, Some(custom_size)) => custom_size,
+		(Some(UploadLimit::Custom), None) => {
+			return Err(anyhow!("Choose a custom size to use Custom."))
+		}
+		(target, _) => target
+			.unwrap_or(UploadLimit::Free)
+			.megabytes()
+			.unwrap_or(10.0),
+	};
+	let target_size = megabytes_to_bytes(target_megabytes)?;
+
+	let attachment = AttachmentOrThumbnail::Attachment(attachment);
+	let compressed = jobs::run(
+		ctx,
+		JobClass::Video,
+		ephemeral,
+		compress_attachment(&ctx.data().http, &attachment, target_size),
+	)
+	.await?;
+
+	ctx.send(compressed.reply().ephemeral(ephemeral)).await?;
+
+	Ok(())
+}
+
+fn megabytes_to_bytes(megabytes: f64) -> Result<u64> {
+	if !megabytes.is_finite() || megabytes <= 0.0 {
+		return Err(anyhow!("The target size has to be more than 0 MB."));
+	}
+	Ok((megabytes * 1024.0 * 1024.0) as u64)
+}
+
+/// The compressed file along with what it looked like before and after.
+struct Compressed {
+	data: Vec<u8>,
+	filename: String,
+	before: MediaInfo,
+	after: MediaInfo,
+	before_size: u64,
+}
+impl Compressed {
+	/// The file with its size, resolution and bitrate before and after.
+	fn reply(self) -> CreateReply {
+		let mut lines = vec![format!(
+			"Size: {}",
+			format_size_change(self.before_size, self.data.len() as u64)
+		)];
+		let resolution = |info: &MediaInfo| {
+			info.video_stream()
+				.and_then(|video| Some((video.width?, video.height?)))
+				.map(|(width, height)| format!("{}×{}", width, height))
+		};
+		if let (Some(before), Some(after)) =
+			(resolution(&self.before), resolution(&self.after))
+		{
+			lines.push(format!("Resolution: `{}` → `{}`", before, after));
+		}
+		if let (Some(before), Some(after)) =
+			(self.before.bit_rate, self.after.bit_rate)
+		{
+			lines.push(format!(
+				"Bitrate: `{} kb/s` → `{} kb/s`",
+				before / 1000,
+				after / 1000
+			));
+		}
+
+		CreateReply::default()
+			.allowed_mentions(CreateAllowedMentions::default())
+			.content(lines.join("\n"))
+			.attachment(CreateAttachment::bytes(self.data, self.filename))
+	}
+}
+
+async fn compress_attachment(
+	client: &reqwest::Client,
+	attachment: &AttachmentOrThumbnail,
+	target_size: u64,
+) -> Result<Compressed> {
+	let workspace = Workspace::new("compress")?;
+	let download = workspace.download(client, attachment).await?;
+	let before = download.info()?;
+	let input_path = download.path;
+	let before_size = fs::metadata(&input_path)?.len();
+
+	let (output_path, extension) =
+		media::compress_file(&input_path, &before, &workspace, target_size)
+			.await?;
+	let after = probe(&output_path).await?;
+
+	Ok(Compressed {
+		data: fs::read(&output_path)?,
+		filename: change_extension(attachment.filename(), &extension)
+			.to_string_lossy()
+			.to_string(),
+		before,
+		after,
+		before_size,
+	})
+}
@@ -0,0 +1,304 @@
+use std::fs;
+
+use crate::{
+	config::{ApplicationContext, Context},
+	convert::{self, ConvertOptions, TargetFormat, DEFAULT_QUALITY},
+	helpers::{
+		change_extension, format_size_change, AttachmentOrThumbnail,
+		ContextExt,
+	},
+	jobs::{self, JobClass},
+	picker::MenuTarget,
+	workspace::Workspace,
+};
+use anyhow::{anyhow, Result};
+use poise::{
+	serenity_prelude::{
+		Attachment, CreateAllowedMentions, CreateAttachment, Message, User,
+	},
+	ChoiceParameter, CreateReply, Modal,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, ChoiceParameter)]
+enum ConvertMode {
+	Lossless,
+	Lossy,
+}
+
+#[derive(Debug, Modal)]
+#[name = "Convert"]
+struct ConvertModal {
+	#[placeholder = "WebP, JXL, AVIF, PNG, JPEG, GIF or APNG. (default: WebP)"]
+	format: Option<String>,
+	#[placeholder = "Lossless, or 0-100 for lossy. (default: lossless)"]
+	quality: Option<String>,
+	#[placeholder = "1-10, where 10 is the slowest and smallest."]
+	effort: Option<String>,
+	#[name = "Max Size"]
+	#[placeholder = "Such as 1920x1080, or 512 for both."]
+	max_size: Option<String>,
+	#[placeholder = "Whether or not to show the message."]
+	ephemeral: Option<String>,
+}
+impl ConvertModal {
+	/// Reads the options typed into the modal.
+	fn options(&self) -> Result<ConvertOptions> {
+		let format = match self.format.as_deref() {
+			Some(format) => TargetFormat::from_name(format.trim())
+				.ok_or_else(|| {
+					anyhow!(
+						"`{}` isn't a format I can convert to.",
+						format.trim()
+					)
+				})?,
+			None => TargetFormat::WebP,
+		};
+		let quality = match self.quality.as_deref().map(str::trim) {
+			None => None,
+			Some(quality) if quality.eq_ignore_ascii_case("lossless") => None,
+			Some(quality) if quality.eq_ignore_ascii_case("lossy") => {
+				Some(DEFAULT_QUALITY)
+			}
+			Some(quality) => Some(
+				quality
+					.parse::<u8>()
+					.ok()
+					.filter(|quality| *quality <= 100)
+					.ok_or_else(|| {
+						anyhow!(
+							"`{}` isn't a quality from 0 to 100.",
+							quality
+						)
+					})?,
+			),
+		};
+		let effort = match self.effort.as_deref().map(str::trim) {
+			Some(effort) => Some(
+				effort
+					.parse::<u8>()
+					.ok()
+					.filter(|effort| (1..=10).contains(effort))
+					.ok_or_else(|| {
+						anyhow!("`{}` isn't an effort from 1 to 10.", effort)
+					})?,
+			),
+			None => None,
+		};
+		let (max_width, max_height) = match self.max_size.as_deref() {
+			Some(max_size) => parse_max_size(max_size)?,
+			None => (None, None),
+		};
+		Ok(ConvertOptions {
+			format,
+			quality,
+			effort: effort.unwrap_or_else(|| format.default_effort()),
+			max_width,
+			max_height,
+		})
+	}
+}
+
+/// Converts an image or animation to another format.
+#[poise::command(
+	context_menu_command = "Convert",
+	owners_only,
+	track_edits,
+	install_context = "User",
+	interaction_context = "Guild|BotDm|PrivateChannel",
+	ephemeral
+)]
+pub async fn convert_context_menu(
+	ctx: ApplicationContext<'_>,
+	#[description = "The message to convert."] message: Message,
+) -> Result<()> {
+	convert_menu(ctx, MenuTarget::Message(&message)).await
+}
+
+/// Converts a user's avatar or banner to another format.
+#[poise::command(
+	context_menu_command = "Profile Convert",
+	owners_only,
+	track_edits,
+	install_context = "User",
+	interaction_context = "Guild|BotDm|PrivateChannel",
+	ephemeral
+)]
+pub async fn convert_user_context_menu(
+	ctx: ApplicationContext<'_>,
+	#[description = "The user with the avatar or banner."] user: User,
+) -> Result<()> {
+	convert_menu(ctx, MenuTarget::User(&user)).await
+}
+
+/// What both Convert menus do once they know where the file comes from.
+async fn convert_menu(
+	ctx: ApplicationContext<'_>,
+	target: MenuTarget<'_>,
+) -> Result<()> {
+	let data = ConvertModal::execute(ctx)
+		.await?
+		.ok_or_else(|| anyhow!("No modal data."))?;
+
+	let ephemeral = match data.ephemeral.as_deref() {
+		Some("false") => false,
+		Some(_) => true,
+		None => ctx.default_ephemeral(false),
+	};
+
+	let options = data.options()?;
+
+	let attachment = &target.pick(ctx).await?;
+
+	let converted = jobs::run(
+		ctx.into(),
+		JobClass::Image,
+		ephemeral,
+		convert_attachment(&ctx.data().http, attachment, &options),
+	)
+	.await?;
+
+	ctx.send(converted.reply().ephemeral(ephemeral)).await?;
+
+	Ok(())
+}
+
+/// Converts an image or animation to another format.
+#[poise::command(
+	slash_command,
+	owners_only,
+	track_edits,
+	install_context = "User",
+	interaction_context = "Guild|BotDm|PrivateChannel",
+	ephemeral
+)]
+pub async fn convert(
+	ctx: Context<'_>,
+	#[description = "The file to convert."] attachment: Attachment,
+	#[description = "The format to convert to."] format: TargetFormat,
+	#[description = "Only WebP, JXL and AVIF have both. (default: lossless, \
+	                 or lossy with a quality)"]
+	mode: Option<ConvertMode>,
+	#[description = "The quality when lossy, from 0 to 100. (default: 85)"]
+	#[min = 0]
+	#[max = 100]
+	quality: Option<u8>,
+	#[description = "How hard to try, from 1 to 10. (default: 10, or 6 for \
+	                 AVIF)"]
+	#[min = 1]
+	#[max = 10]
+	effort: Option<u8>,
+	#[description = "The widest it can be. It's never scaled up."]
+	#[min = 1]
+	max_width: Option<u32>,
+	#[description = "The tallest it can be. It's never scaled up."]
+	#[min = 1]
+	max_height: Option<u32>,
+	#[description = "Whether or not to show the message."] ephemeral: Option<
+		bool,
+	>,
+) -> Result<()> {
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(false));
+	if ephemeral {
+		ctx.defer_ephemeral().await?;
+	} else {
+		ctx.defer().await?;
+	}
+
+	let quality = match mode {
+		Some(ConvertMode::Lossless) => None,
+		Some(ConvertMode::Lossy) => Some(quality.unwrap_or(DEFAULT_QUALITY)),
+		None => quality,
+	};
+	let options = ConvertOptions {
+		format,
+		quality,
+		effort: effort.unwrap_or_else(|| format.default_effort()),
+		max_width,
+		max_height,
+	};
+
+	let attachment = AttachmentOrThumbnail::Attachment(attachment);
+	let converted = jobs::run(
+		ctx,
+		JobClass::Image,
+		ephemeral,
+		convert_attachment(&ctx.data().http, &attachment, &options),
+	)
+	.await?;
+
+	ctx.send(converted.reply().ephemeral(ephemeral)).await?;
+
+	Ok(())
+}
+
+/// Such as `1920x1080`, `1920×1080` or `512` for both.
+fn parse_max_size(text: &str) -> Result<(Option<u32>, Option<u32>)> {
+	let text = text.trim();
+	let parse = |dimension: &str| {
+		dimension
+			.trim()
+			.parse::<u32>()
+			.ok()
+			.filter(|dimension| *dimension > 0)
+			.ok_or_else(|| anyhow!("`{}` isn't a size like 1920x1080.", text))
+	};
+	match text.split_once(['x', 'X', '×']) {
+		Some((width, height)) => {
+			Ok((Some(parse(width)?), Some(parse(height)?)))
+		}
+		None => {
+			let size = parse(text)?;
+			Ok((Some(size), Some(size)))
+		}
+	}
+}
+
+/// The converted file and how big the original was.
+struct Converted {
+	data: Vec<u8>,
+	filename: String,
+	before_size: u64,
+}
+impl Converted {
+	fn reply(self) -> CreateReply {
+		CreateReply::default()
+			.allowed_mentions(CreateAllowedMentions::default())
+			.content(format!(
+				"Size: {}",
+				format_size_change(self.before_size, self.data.len() as u64)
+			))
+			.attachment(CreateAttachment::bytes(self.data, self.filename))
+	}
+}
+
+async fn convert_attachment(
+	client: &reqwest::Client,
+	attachment: &AttachmentOrThumbnail,
+	options: &ConvertOptions,
+) -> Result<Converted> {
+	let workspace = Workspace::new("convert")?;
+	let download = workspace.download(client, attachment).await?;
+	let info = download.info()?;
+	let before_size = fs::metadata(&download.path)?.len();
+
+	let output_path = convert::convert_file(
+		&download.path,
+		download.format,
+		&info,
+		&workspace,
+		options,
+	)
+	.await?;
+
+	Ok(Converted {
+		data: fs::read(&output_path)?,
+		filename: change_extension(
+			attachment.filename(),
+			options.format.extension(),
+		)
+		.to_string_lossy()
+		.to_string(),
+		before_size,
+	})
+}
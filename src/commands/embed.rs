@@ -8,7 +8,10 @@ use poise::{
 	CreateReply,
 };
 
-use crate::{helpers::easy_set_file_name, config::Context};
+use crate::{
+	config::Context,
+	helpers::{easy_set_file_name, ContextExt},
+};
 
 /// Builds an embed and sends it.
 /// RON representation can be used to send multiple embeds.
@@ -53,7 +56,7 @@ pub async fn embed(
 		bool,
 	>,
 ) -> Result<()> {
-	let ephemeral = ephemeral.unwrap_or(false);
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(false));
 	if ephemeral {
 		ctx.defer_ephemeral().await?;
 	} else {
@@ -84,8 +87,7 @@ pub async fn embed(
 			if let Some(color) = color {
 				embed.colour = Some(Colour::new(ron::from_str(&color)?));
 			} else {
-				embed.colour =
-					Some(ctx.data().config.embed_color.clone().into());
+				embed.colour = Some(ctx.settings().embed_color.into());
 			}
 			if let Some(footer) = footer {
 				embed.footer = Some(ron::from_str(&footer)?);
@@ -94,9 +96,12 @@ pub async fn embed(
 				let mut downloaded_attachment =
 					CreateAttachment::url(ctx.http(), &attachment.url)
 						.await?;
-				downloaded_attachment.filename =
-					easy_set_file_name(&attachment.filename, "image")
-						.to_string();
+				downloaded_attachment.filename = easy_set_file_name(
+					&attachment.filename,
+					"image",
+					&downloaded_attachment.data,
+				)
+				.to_string();
 				downloaded_attachment.description = attachment.description;
 				reply = reply.attachment(downloaded_attachment.clone());
 				let mut image = ron::from_str::<EmbedImage>("(url:\"\")")?;
@@ -110,9 +115,12 @@ pub async fn embed(
 				let mut downloaded_attachment =
 					CreateAttachment::url(ctx.http(), &attachment.url)
 						.await?;
-				downloaded_attachment.filename =
-					easy_set_file_name(&attachment.filename, "thumbnail")
-						.to_string();
+				downloaded_attachment.filename = easy_set_file_name(
+					&attachment.filename,
+					"thumbnail",
+					&downloaded_attachment.data,
+				)
+				.to_string();
 				downloaded_attachment.description = attachment.description;
 				reply = reply.attachment(downloaded_attachment.clone());
 				let mut image =
@@ -137,9 +145,8 @@ pub async fn embed(
 	};
 
 	for mut embed in embeds {
-		embed.colour = embed
-			.colour
-			.or(Some(ctx.data().config.embed_color.clone().into()));
+		embed.colour =
+			embed.colour.or(Some(ctx.settings().embed_color.into()));
 		reply = reply.embed(embed.into());
 	}
 
@@ -1,7 +1,10 @@
 use anyhow::Result;
 use poise::{serenity_prelude::CreateAllowedMentions, CreateReply};
 
-use crate::{helpers::escape_markdown, config::Context};
+use crate::{
+	config::Context,
+	helpers::{escape_markdown, ContextExt},
+};
 
 /// Escapes basic markdown characters.
 #[poise::command(
@@ -19,7 +22,7 @@ pub async fn escape(
 		bool,
 	>,
 ) -> Result<()> {
-	let ephemeral = ephemeral.unwrap_or(false);
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(false));
 	if ephemeral {
 		ctx.defer_ephemeral().await?;
 	} else {
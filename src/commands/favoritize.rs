@@ -1,14 +1,21 @@
-use std::{env, fs, path::PathBuf, process, sync::LazyLock};
+use std::{fs, path::PathBuf, process, sync::LazyLock};
 
 use crate::{
-	helpers::{safe_delete, AttachmentOrThumbnail},
-	os_command::run_os_command,
 	config::{ApplicationContext, Context},
+	convert::DEFAULT_QUALITY,
+	helpers::{AttachmentOrThumbnail, ContextExt},
+	jobs::{self, JobClass},
+	media::{ffmpeg_command, ffmpeg_input, run_ffmpeg_with_progress},
+	os_command::run_os_command,
+	picker::MenuTarget,
+	probe::MediaType,
+	sniff::FileFormat,
+	workspace::Workspace,
 };
 use anyhow::{anyhow, Result};
 use poise::{
 	serenity_prelude::{
-		Attachment, CreateAllowedMentions, CreateAttachment, Message,
+		Attachment, CreateAllowedMentions, CreateAttachment, Message, User,
 	},
 	CreateReply, Modal,
 };
@@ -27,9 +34,6 @@ static TRANSPARENT_1X1_IMAGE: LazyLock<PathBuf> = LazyLock::new(|| {
 #[derive(Debug, Modal)]
 #[name = "Favoritize Image"]
 struct FavoritizeModal {
-	#[name = "Attachment Index"]
-	#[placeholder = "The index of the attachment to use. (default: 0)"]
-	attachment_index: Option<String>,
 	#[placeholder = "Whether or not to show the message."]
 	ephemeral: Option<String>,
 }
@@ -47,6 +51,31 @@ pub async fn favoritize_context_menu(
 	ctx: ApplicationContext<'_>,
 	#[description = "The message to turn into a favoritable image."]
 	message: Message,
+) -> Result<()> {
+	favoritize_menu(ctx, MenuTarget::Message(&message)).await
+}
+
+/// Converts a user's avatar or banner into a 2 frame WebP so that it can be added to your favorited GIFs list.
+#[poise::command(
+	context_menu_command = "Profile Favoritize",
+	owners_only,
+	track_edits,
+	install_context = "User",
+	interaction_context = "Guild|BotDm|PrivateChannel",
+	ephemeral
+)]
+pub async fn favoritize_user_context_menu(
+	ctx: ApplicationContext<'_>,
+	#[description = "The user with the avatar or banner."] user: User,
+) -> Result<()> {
+	favoritize_menu(ctx, MenuTarget::User(&user)).await
+}
+
+/// What both Favoritize menus do once they know where the image comes
+/// from.
+async fn favoritize_menu(
+	ctx: ApplicationContext<'_>,
+	target: MenuTarget<'_>,
 ) -> Result<()> {
 	let data = FavoritizeModal::execute(ctx)
 		.await?
@@ -55,49 +84,24 @@ pub async fn favoritize_context_menu(
 	let ephemeral = match data.ephemeral.as_deref() {
 		Some("false") => false,
 		Some(_) => true,
-		None => false,
+		None => ctx.default_ephemeral(false),
 	};
 
-	let attachments: Vec<AttachmentOrThumbnail> = message
-		.attachments
-		.iter()
-		.map(|a| AttachmentOrThumbnail::Attachment(a.clone()))
-		.chain(message.embeds.iter().filter_map(|e| {
-			if let Some(thumbnail) = &e.thumbnail {
-				if thumbnail.proxy_url.is_some() {
-					Some(AttachmentOrThumbnail::Embed(thumbnail.clone()))
-				} else {
-					None
-				}
-			} else {
-				None
-			}
-		}))
-		.collect();
-
-	// Get the attachment to turn into a favoritable image.
-	let attachment_index = match data.attachment_index.as_deref() {
-		Some(attachment_index) => attachment_index.parse::<usize>()?,
-		None => 0,
-	};
-	let attachment = attachments.get(attachment_index).ok_or_else(|| {
-		anyhow!(
-			"You chose attachment {} but there {} only {} attachment{}.",
-			attachment_index + 1,
-			if attachments.len() == 1 { "is" } else { "are" },
-			attachments.len(),
-			if attachments.len() == 1 { "" } else { "s" },
-		)
-	})?;
+	let attachment = &target.pick(ctx).await?;
 
 	let mut reply = CreateReply::default()
 		.allowed_mentions(CreateAllowedMentions::default())
 		.ephemeral(ephemeral);
 
-	let (new_image_data, new_image_name) = convert_to_animated_webp(
-		&ctx.data().http,
-		attachment,
-		&attachment.filename(),
+	let (new_image_data, new_image_name) = jobs::run(
+		ctx.into(),
+		JobClass::Image,
+		ephemeral,
+		convert_to_animated_webp(
+			&ctx.data().http,
+			attachment,
+			&attachment.filename(),
+		),
 	)
 	.await?;
 	reply = reply
@@ -125,7 +129,7 @@ pub async fn favoritize(
 		bool,
 	>,
 ) -> Result<()> {
-	let ephemeral = ephemeral.unwrap_or(false);
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(false));
 	if ephemeral {
 		ctx.defer_ephemeral().await?;
 	} else {
@@ -137,10 +141,15 @@ pub async fn favoritize(
 		.ephemeral(ephemeral);
 
 	let attachment = AttachmentOrThumbnail::Attachment(attachment);
-	let (new_image_data, new_image_name) = convert_to_animated_webp(
-		&ctx.data().http,
-		&attachment,
-		&attachment.filename(),
+	let (new_image_data, new_image_name) = jobs::run(
+		ctx,
+		JobClass::Image,
+		ephemeral,
+		convert_to_animated_webp(
+			&ctx.data().http,
+			&attachment,
+			&attachment.filename(),
+		),
 	)
 	.await?;
 	reply = reply
@@ -156,46 +165,90 @@ pub async fn convert_to_animated_webp(
 	attachment: &AttachmentOrThumbnail,
 	attachment_name: &str,
 ) -> Result<(Vec<u8>, String)> {
-	let image_path_template = env::temp_dir();
-	let image_input = image_path_template.join(attachment_name);
-	let mut image_output = image_path_template.join(attachment_name);
+	let workspace = Workspace::new("favoritize")?;
+	let mut image_output = workspace.output_path(attachment_name);
 	image_output.set_extension("webp");
 
-	fs::write(&image_input, attachment.download(client).await?)?;
+	let download = workspace.download(client, attachment).await?;
+	let info = download.info()?;
+	let image_input = download.path;
 
-	// img2webp -near_lossless 100 -sharp_yuv -v -loop 0 input.png -d 1 -lossless -q 100 -m 6 -o output.webp
-
-	// First, convert the image to a WebP with the best quality possible.
-	let mut img2webp_command = process::Command::new("img2webp");
-	img2webp_command.args([
-		"-v",
-		"-sharp_yuv",
-		"-loop",
-		"0",
-		image_input.to_str().unwrap(),
-		"-d",
-		"1",
-		"-lossless",
-		"-q",
-		"100",
-		"-m",
-		"6",
-		"-o",
-		image_output.to_str().unwrap(),
-	]);
-	let img2webp_output = run_os_command("img2webp", img2webp_command)?;
+	// Animations keep their frames, with the same tools /convert uses for
+	// them. Only a still needs the transparent frame added below.
+	let is_gif = download.format == Some(FileFormat::Gif);
+	let is_video = info.media_type() == MediaType::Video;
+	let encode_output = if is_gif {
+		let mut gif2webp_command = process::Command::new("gif2webp");
+		gif2webp_command
+			.args(["-v", "-mt", "-m", "6"])
+			.arg(&image_input)
+			.arg("-o")
+			.arg(&image_output);
+		run_os_command("gif2webp", gif2webp_command).await?
+	} else if is_video {
+		// Videos are lossy already, so a lossless WebP is only bigger.
+		let mut ffmpeg_command = ffmpeg_command();
+		ffmpeg_command.arg("-y").args(ffmpeg_input(&image_input));
+		ffmpeg_command.args([
+			"-an",
+			"-c:v",
+			"libwebp_anim",
+			"-compression_level",
+			"6",
+			"-loop",
+			"0",
+			"-lossless",
+			"0",
+			"-quality",
+			&DEFAULT_QUALITY.to_string(),
+		]);
+		ffmpeg_command.args(["-f", "webp"]).arg(&image_output);
+		run_ffmpeg_with_progress(ffmpeg_command, info.duration).await?
+	} else {
+		// img2webp -near_lossless 100 -sharp_yuv -v -loop 0 input.png -d 1 -lossless -q 100 -m 6 -o output.webp
 
-	if !img2webp_output.status.success() {
-		// Delete the files.
-		safe_delete(&image_input)?;
-		safe_delete(&image_output)?;
+		// First, convert the image to a WebP with the best quality possible.
+		let mut img2webp_command = process::Command::new("img2webp");
+		img2webp_command.args([
+			"-v",
+			"-sharp_yuv",
+			"-loop",
+			"0",
+			image_input.to_str().unwrap(),
+			"-d",
+			"1",
+			"-lossless",
+			"-q",
+			"100",
+			"-m",
+			"6",
+			"-o",
+			image_output.to_str().unwrap(),
+		]);
+		run_os_command("img2webp", img2webp_command).await?
+	};
 
+	if !encode_output.status.success() {
 		return Err(anyhow!(
 			"```\n{}\n```",
-			String::from_utf8_lossy(&img2webp_output.stderr)
+			String::from_utf8_lossy(&encode_output.stderr)
 		));
 	}
 
+	let read_output = || -> Result<(Vec<u8>, String)> {
+		Ok((
+			fs::read(&image_output)?,
+			image_output
+				.file_name()
+				.unwrap()
+				.to_string_lossy()
+				.to_string(),
+		))
+	};
+	if is_gif || is_video {
+		return read_output();
+	}
+
 	// webpmux -frame output.webp +0+0+0+1 -frame output.webp +0+0+0+1 -loop 0 -o output.webp
 
 	// Then convert that one WebP into another WebP with two duplicate frames.
@@ -215,28 +268,11 @@ pub async fn convert_to_animated_webp(
 		"-o",
 		image_output.to_str().unwrap(),
 	]);
-	let webpmux_output = run_os_command("webpmux", webpmux_command)?;
+	let webpmux_output = run_os_command("webpmux", webpmux_command).await?;
 
 	if webpmux_output.status.success() {
-		let data = fs::read(&image_output)?;
-
-		// Delete the files.
-		safe_delete(&image_input)?;
-		safe_delete(&image_output)?;
-
-		Ok((
-			data,
-			image_output
-				.file_name()
-				.unwrap()
-				.to_string_lossy()
-				.to_string(),
-		))
+		read_output()
 	} else {
-		// Delete the files.
-		safe_delete(&image_input)?;
-		safe_delete(&image_output)?;
-
 		Err(anyhow!(
 			"```\n{}\n```",
 			String::from_utf8_lossy(&webpmux_output.stderr)
@@ -1,9 +1,11 @@
-use std::{env, fs, process};
+use std::fs;
 
 use crate::{
-	helpers::{safe_delete, AttachmentOrThumbnail},
-	os_command::run_os_command,
 	config::Context,
+	helpers::{AttachmentOrThumbnail, ContextExt},
+	jobs::{self, JobClass},
+	media, sandbox,
+	workspace::Workspace,
 };
 use anyhow::{anyhow, Result};
 use inline_format::format;
@@ -11,7 +13,6 @@ use poise::{
 	serenity_prelude::{Attachment, CreateAllowedMentions, CreateAttachment},
 	CreateReply,
 };
-use rand::Rng;
 
 /// Runs a basic FFmpeg command on uploaded media.
 #[poise::command(
@@ -38,7 +39,7 @@ pub async fn ffmpeg(
 		bool,
 	>,
 ) -> Result<()> {
-	let ephemeral = ephemeral.unwrap_or(false);
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(false));
 	if ephemeral {
 		ctx.defer_ephemeral().await?;
 	} else {
@@ -64,8 +65,13 @@ pub async fn ffmpeg(
 	.into_iter()
 	.flatten()
 	.collect();
-	let new_image_data =
-		run_ffmpeg(&ctx, &attachments, &flags, &output_name).await?;
+	let new_image_data = jobs::run(
+		ctx,
+		JobClass::Video,
+		ephemeral,
+		run_ffmpeg(&ctx, &attachments, &flags, &output_name),
+	)
+	.await?;
 	reply = reply
 		.attachment(CreateAttachment::bytes(new_image_data, output_name));
 
@@ -74,63 +80,63 @@ pub async fn ffmpeg(
 	Ok(())
 }
 
+/// Runs ffmpeg with the user's flags inside its own workspace, which is
+/// deleted afterwards. Arguments that could reach files outside of it or
+/// open network protocols are rejected before anything runs.
 pub async fn run_ffmpeg(
 	ctx: &Context<'_>,
 	attachments: &Vec<AttachmentOrThumbnail>,
 	flags: &str,
 	output_name: &str,
 ) -> Result<Vec<u8>> {
-	let path_template = env::temp_dir();
-	// Generate a unique name for each input file.
-	let mut input_files = Vec::new();
-	for attachment in attachments {
-		let num: u32 = rand::thread_rng().gen();
-		let attachment_name = format!(num, "_", attachment.filename());
-
-		// Save each file.
-		let input_file_path = path_template.join(attachment_name);
-		input_files.push(input_file_path.clone());
-		fs::write(
-			&input_file_path,
-			attachment.download(&ctx.data().http).await?,
-		)?;
-	}
-
-	// Output file in temp dir.
-	let output_file_path = path_template.join(output_name);
-
-	let mut ffmpeg_command = process::Command::new("ffmpeg");
-
-	for input_file in &input_files {
-		ffmpeg_command.args(["-i", input_file.to_str().unwrap()]);
+	sandbox::check_output_name(output_name)?;
+	let flags = flags.split_whitespace().collect::<Vec<_>>();
+	sandbox::check_ffmpeg_args(flags.iter().copied())?;
+
+	let workspace = Workspace::new("ffmpeg")?;
+	let protocol_whitelist = sandbox::ALLOWED_PROTOCOLS.join(",");
+
+	let mut ffmpeg_command = media::ffmpeg_command();
+	ffmpeg_command.current_dir(workspace.path()).arg("-nostdin");
+
+	// The progress is measured against the first input.
+	let mut duration = None;
+	for (index, attachment) in attachments.iter().enumerate() {
+		let download =
+			workspace.download(&ctx.data().http, attachment).await?;
+		if index == 0 {
+			duration = download.info().ok().and_then(|info| info.duration);
+		}
+		ffmpeg_command.args(media::ffmpeg_input(&download.path));
 	}
-	if !flags.is_empty() {
-		ffmpeg_command.args(flags.split_whitespace());
+	for flag in flags {
+		// Inputs from the flags get the same whitelist.
+		if flag == "-i" {
+			ffmpeg_command.args(["-protocol_whitelist", &protocol_whitelist]);
+		}
+		ffmpeg_command.arg(flag);
 	}
-	ffmpeg_command.args([output_file_path.to_str().unwrap()]);
+	// `file:` keeps the path from being read as a protocol.
+	let output_path = workspace.output_path(output_name);
+	ffmpeg_command
+		.args(["-protocol_whitelist", &protocol_whitelist])
+		.arg(format!("file:", output_path.display()));
 
-	let ffmpeg_command_output = run_os_command("ffmpeg", ffmpeg_command)?;
+	let ffmpeg_command_output =
+		media::run_ffmpeg_with_progress(ffmpeg_command, duration).await?;
 
 	if !ffmpeg_command_output.status.success() {
-		// Delete the files.
-		for input_file in input_files {
-			safe_delete(&input_file)?;
-		}
-		safe_delete(&output_file_path)?;
-
 		return Err(anyhow!(
 			"```\n{}\n```",
 			String::from_utf8_lossy(&ffmpeg_command_output.stderr)
 		));
 	}
 
-	let data = fs::read(&output_file_path)?;
-
-	// Delete the files.
-	for input_file in input_files {
-		safe_delete(&input_file)?;
+	if !fs::symlink_metadata(&output_path)?.is_file() {
+		return Err(anyhow!(
+			"ffmpeg didn't write {} as a file.",
+			output_name
+		));
 	}
-	safe_delete(&output_file_path)?;
-
-	Ok(data)
+	Ok(fs::read(&output_path)?)
 }
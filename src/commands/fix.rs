@@ -1,4 +1,4 @@
-use crate::config::Context;
+use crate::{config::Context, helpers::ContextExt};
 use anyhow::Result;
 use poise::{
 	serenity_prelude::CreateAllowedMentions, ChoiceParameter, CreateReply,
@@ -86,7 +86,7 @@ pub async fn fix(
 	let instagram_view = instagram_view.unwrap_or(InstagramView::Default);
 	let tiktok_view = tiktok_view.unwrap_or(TikTokView::Default);
 	let x_bsky_view = x_bsky_view.unwrap_or(XBSkyView::Default);
-	let ephemeral = ephemeral.unwrap_or(false);
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(false));
 	if ephemeral {
 		ctx.defer_ephemeral().await?;
 	} else {
@@ -1,7 +1,10 @@
 use anyhow::Result;
 use poise::{serenity_prelude::CreateAllowedMentions, CreateReply};
 
-use crate::config::Context;
+use crate::{
+	config::Context,
+	helpers::{ContextExt, RequestBuilderExt},
+};
 
 /// Flips a nickel using a true random number generator.
 #[poise::command(
@@ -18,19 +21,20 @@ pub async fn flip(
 		bool,
 	>,
 ) -> Result<()> {
-	let ephemeral = ephemeral.unwrap_or(false);
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(false));
 	if ephemeral {
 		ctx.defer_ephemeral().await?;
 	} else {
 		ctx.defer().await?;
 	}
 
-	let api_key =
-		if let Some(api_key) = ctx.data().config.randomorg_api_key.clone() {
-			api_key
-		} else {
-			return Err(anyhow::anyhow!("RANDOM.ORG API key not set."));
-		};
+	let api_key = if let Some(api_key) =
+		ctx.data().config().randomorg_api_key.clone()
+	{
+		api_key
+	} else {
+		return Err(anyhow::anyhow!("RANDOM.ORG API key not set."));
+	};
 
 	let response = ctx
 		.data()
@@ -49,7 +53,7 @@ pub async fn flip(
 			},
 			"id": 1
 		}))
-		.send()
+		.send_measured()
 		.await?
 		.json::<serde_json::Value>()
 		.await?;
@@ -1,7 +1,7 @@
 use anyhow::Result;
 use poise::{serenity_prelude::CreateAllowedMentions, CreateReply};
 
-use crate::config::Context;
+use crate::{config::Context, helpers::ContextExt};
 
 /// Sends a formatted link to a GitHub profile or repository.
 #[poise::command(
@@ -20,7 +20,7 @@ pub async fn github(
 		bool,
 	>,
 ) -> Result<()> {
-	let ephemeral = ephemeral.unwrap_or(false);
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(false));
 	if ephemeral {
 		ctx.defer_ephemeral().await?;
 	} else {
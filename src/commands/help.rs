@@ -1,6 +1,6 @@
 use anyhow::Error;
 
-use crate::config::Context;
+use crate::{config::Context, helpers::ContextExt};
 
 /// Shows the help menu. Ephemeral by default.
 #[poise::command(
@@ -20,7 +20,7 @@ pub async fn help(
 		bool,
 	>,
 ) -> Result<(), Error> {
-	let ephemeral = ephemeral.unwrap_or(true);
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(true));
 	if ephemeral {
 		ctx.defer_ephemeral().await?;
 	} else {
@@ -0,0 +1,540 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::{anyhow, Result};
+use poise::{
+	futures_util::{stream::FuturesUnordered, StreamExt},
+	serenity_prelude::{
+		ActionRowComponent, ButtonStyle, CommandInteraction,
+		ComponentInteraction, CreateActionRow, CreateAllowedMentions,
+		CreateButton, CreateEmbed, CreateInputText,
+		CreateInteractionResponse, CreateInteractionResponseMessage,
+		CreateModal, FullEvent, InputTextStyle, Interaction,
+		ModalInteractionCollector,
+	},
+	CreateReply,
+};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+	config::Context,
+	helpers::ContextExt,
+	history::{HistoryEntry, Outcome},
+	storage::HistoryFilter,
+};
+use tracing::warn;
+
+/// How long the buttons keep working.
+const BUTTON_TIMEOUT: Duration = Duration::from_secs(60 * 10);
+/// Discord allows five inputs per modal.
+const MAX_MODAL_INPUTS: usize = 5;
+
+/// Option types that can be typed into a modal. The rest, such as
+/// attachments and users, keep their previous value.
+const STRING_OPTION: u64 = 3;
+const INTEGER_OPTION: u64 = 4;
+const BOOLEAN_OPTION: u64 = 5;
+const NUMBER_OPTION: u64 = 10;
+
+/// Lists your past command invocations so you can run them again.
+/// Ephemeral by default.
+///
+/// Re-run repeats the invocation as is, and Edit opens its arguments in a
+/// form first. Attachments from old invocations may have expired.
+#[poise::command(
+	slash_command,
+	owners_only,
+	track_edits,
+	install_context = "User",
+	interaction_context = "Guild|BotDm|PrivateChannel",
+	ephemeral
+)]
+pub async fn history(
+	ctx: Context<'_>,
+	#[description = "Only show this command, such as ffmpeg."]
+	command: Option<String>,
+	#[description = "Only show invocations with this outcome."]
+	outcome: Option<Outcome>,
+	#[description = "How many invocations to show. (default: 10)"]
+	#[min = 1]
+	#[max = 10]
+	limit: Option<u32>,
+	#[description = "Whether or not to show the message."] ephemeral: Option<
+		bool,
+	>,
+) -> Result<()> {
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(true));
+	if ephemeral {
+		ctx.defer_ephemeral().await?;
+	} else {
+		ctx.defer().await?;
+	}
+
+	let filter = HistoryFilter {
+		command,
+		outcome,
+		limit: limit.unwrap_or(10).clamp(1, 10),
+	};
+	let entries =
+		ctx.data().storage.history(ctx.author().id, &filter).await?;
+
+	let mut reply = CreateReply::default()
+		.allowed_mentions(CreateAllowedMentions::default())
+		.ephemeral(ephemeral);
+
+	if entries.is_empty() {
+		reply = reply.content("There are no matching invocations.");
+		ctx.send(reply).await?;
+		return Ok(());
+	}
+
+	let description = entries
+		.iter()
+		.enumerate()
+		.map(|(index, entry)| entry_line(index + 1, entry))
+		.collect::<Vec<_>>()
+		.join("\n");
+	reply = reply
+		.embed(
+			CreateEmbed::new()
+				.title("History")
+				.description(description)
+				.color(ctx.settings().embed_color),
+		)
+		.components(entry_buttons(&entries));
+
+	let message = ctx.send(reply).await?.into_message().await?;
+
+	// Replays run alongside the collector, so a long command or an open
+	// modal doesn't stop the other buttons from working.
+	let mut clicks = message
+		.await_component_interactions(&ctx.serenity_context().shard)
+		.author_id(ctx.author().id)
+		.timeout(BUTTON_TIMEOUT)
+		.stream();
+	let mut replays = FuturesUnordered::new();
+	loop {
+		tokio::select! {
+			interaction = clicks.next() => {
+				let Some(interaction) = interaction else { break };
+				replays.push(handle_click(ctx, interaction));
+			}
+			Some(()) = replays.next() => {}
+		}
+	}
+	while replays.next().await.is_some() {}
+
+	Ok(())
+}
+
+/// Errors are shown to whoever clicked instead of ending the collector.
+async fn handle_click(ctx: Context<'_>, interaction: ComponentInteraction) {
+	let Err(e) = replay_entry(ctx, &interaction).await else {
+		return;
+	};
+	let content = format!("Couldn't run that again: {}", e);
+	// Fails if the click was already responded to, such as with a modal.
+	if let Err(respond_error) =
+		respond_ephemeral(ctx, &interaction, content).await
+	{
+		warn!(
+			"Failed to replay a history entry: {} (and to say so: {})",
+			e, respond_error
+		);
+	}
+}
+
+async fn replay_entry(
+	ctx: Context<'_>,
+	interaction: &ComponentInteraction,
+) -> Result<()> {
+	let Some((action, id)) = interaction.data.custom_id.split_once(':')
+	else {
+		return Ok(());
+	};
+	let entry = match id.parse() {
+		Ok(id) => {
+			ctx.data()
+				.storage
+				.history_entry(ctx.author().id, id)
+				.await?
+		}
+		Err(_) => None,
+	};
+	let Some(entry) = entry else {
+		return respond_ephemeral(
+			ctx,
+			interaction,
+			"That entry doesn't exist.",
+		)
+		.await;
+	};
+
+	match action {
+		"rerun" => replay(ctx, interaction, entry.data).await,
+		"edit" => edit_and_replay(ctx, interaction, entry).await,
+		_ => Ok(()),
+	}
+}
+
+fn entry_line(number: usize, entry: &HistoryEntry) -> String {
+	let outcome = match entry.outcome {
+		Outcome::Success => String::new(),
+		Outcome::Error => " **failed**".to_string(),
+		Outcome::Panic => " **crashed**".to_string(),
+	};
+	let mut arguments = entry.arguments();
+	if arguments.chars().count() > 100 {
+		arguments = arguments.chars().take(100).collect::<String>() + "…";
+	}
+	format!(
+		"**{}.** `{}` {} <t:{}:R> ({:.1}s){}",
+		number,
+		entry.command,
+		if arguments.is_empty() {
+			String::new()
+		} else {
+			format!("`{}`", arguments.replace('`', "'"))
+		},
+		entry.invoked_at,
+		entry.duration.as_secs_f64(),
+		outcome
+	)
+}
+
+/// Two buttons per entry, two entries per row.
+fn entry_buttons(entries: &[HistoryEntry]) -> Vec<CreateActionRow> {
+	entries
+		.chunks(2)
+		.enumerate()
+		.map(|(row, entries)| {
+			let buttons = entries
+				.iter()
+				.enumerate()
+				.flat_map(|(column, entry)| {
+					let number = row * 2 + column + 1;
+					let mut buttons = vec![CreateButton::new(format!(
+						"rerun:{}",
+						entry.id
+					))
+					.label(format!("Re-run {}", number))];
+					// Context menus don't have arguments to edit.
+					if entry.data["target_id"].is_null() {
+						buttons.push(
+							CreateButton::new(format!("edit:{}", entry.id))
+								.label(format!("Edit {}", number))
+								.style(ButtonStyle::Secondary),
+						);
+					}
+					buttons
+				})
+				.collect();
+			CreateActionRow::Buttons(buttons)
+		})
+		.collect()
+}
+
+async fn respond_ephemeral(
+	ctx: Context<'_>,
+	interaction: &ComponentInteraction,
+	content: impl Into<String>,
+) -> Result<()> {
+	interaction
+		.create_response(
+			ctx,
+			CreateInteractionResponse::Message(
+				CreateInteractionResponseMessage::new()
+					.content(content)
+					.ephemeral(true),
+			),
+		)
+		.await?;
+	Ok(())
+}
+
+/// Runs the command again as if `interaction` was the original slash
+/// command, so the command responds to the button or modal.
+///
+/// There's no way to create a command interaction, so this rewrites the
+/// button or modal interaction's JSON into one, swapping in the stored
+/// `data`. The ID and token are the button's, which is what lets the
+/// command respond, while `resolved` is whatever the original invocation
+/// had, so its attachment links may have expired.
+async fn replay(
+	ctx: Context<'_>,
+	interaction: &impl Serialize,
+	data: Value,
+) -> Result<()> {
+	let mut value = serde_json::to_value(interaction)?;
+	value["type"] = Value::from(2);
+	value["data"] = data;
+	let interaction = serde_json::from_value::<CommandInteraction>(value)?;
+
+	poise::dispatch_event(
+		ctx.framework(),
+		ctx.serenity_context(),
+		FullEvent::InteractionCreate {
+			interaction: Interaction::Command(interaction),
+		},
+	)
+	.await;
+	Ok(())
+}
+
+/// An argument that can be changed in the modal.
+struct EditableOption {
+	name: String,
+	kind: u64,
+	required: bool,
+	/// Choice names and the values Discord sends for them.
+	choices: Vec<(String, Value)>,
+	/// What the modal is pre-filled with.
+	current: Option<String>,
+}
+
+async fn edit_and_replay(
+	ctx: Context<'_>,
+	interaction: &ComponentInteraction,
+	entry: HistoryEntry,
+) -> Result<()> {
+	let options = editable_options(ctx, &entry)?;
+	if options.is_empty() {
+		return replay(ctx, interaction, entry.data).await;
+	}
+
+	let custom_id = format!("history_edit:{}", interaction.id);
+	let inputs = options
+		.iter()
+		.map(|option| {
+			let mut label = option.name.clone();
+			if !option.choices.is_empty() {
+				label = format!("{} (choice)", label);
+			} else if option.kind == BOOLEAN_OPTION {
+				label = format!("{} (true or false)", label);
+			}
+			let mut input = CreateInputText::new(
+				if option.kind == STRING_OPTION {
+					InputTextStyle::Paragraph
+				} else {
+					InputTextStyle::Short
+				},
+				label.chars().take(45).collect::<String>(),
+				&option.name,
+			)
+			.required(option.required);
+			if let Some(current) = &option.current {
+				input = input.value(current);
+			}
+			if !option.choices.is_empty() {
+				let names = option
+					.choices
+					.iter()
+					.map(|(name, _)| name.as_str())
+					.collect::<Vec<_>>()
+					.join(", ");
+				input = input
+					.placeholder(names.chars().take(100).collect::<String>());
+			}
+			CreateActionRow::InputText(input)
+		})
+		.collect();
+	let modal = CreateModal::new(
+		&custom_id,
+		format!("Edit /{}", entry.command)
+			.chars()
+			.take(45)
+			.collect::<String>(),
+	)
+	.components(inputs);
+	interaction
+		.create_response(ctx, CreateInteractionResponse::Modal(modal))
+		.await?;
+
+	let Some(submission) =
+		ModalInteractionCollector::new(&ctx.serenity_context().shard)
+			.custom_ids(vec![custom_id])
+			.author_id(ctx.author().id)
+			.timeout(BUTTON_TIMEOUT)
+			.await
+	else {
+		return Ok(());
+	};
+
+	let values = submission
+		.data
+		.components
+		.iter()
+		.flat_map(|row| &row.components)
+		.filter_map(|component| match component {
+			ActionRowComponent::InputText(input) => Some((
+				input.custom_id.clone(),
+				input.value.clone().unwrap_or_default(),
+			)),
+			_ => None,
+		})
+		.collect::<HashMap<_, _>>();
+
+	match apply_edits(entry.data, &options, &values) {
+		Ok(data) => replay(ctx, &submission, data).await,
+		Err(e) => {
+			submission
+				.create_response(
+					ctx,
+					CreateInteractionResponse::Message(
+						CreateInteractionResponseMessage::new()
+							.content(e.to_string())
+							.ephemeral(true),
+					),
+				)
+				.await?;
+			Ok(())
+		}
+	}
+}
+
+/// The command's typeable options, the ones that were used first.
+fn editable_options(
+	ctx: Context<'_>,
+	entry: &HistoryEntry,
+) -> Result<Vec<EditableOption>> {
+	let command = ctx
+		.framework()
+		.options()
+		.commands
+		.iter()
+		.find(|command| {
+			command.name == entry.command && command.slash_action.is_some()
+		})
+		.ok_or_else(|| {
+			anyhow!("/{} isn't available anymore.", entry.command)
+		})?;
+	let definition = command
+		.create_as_slash_command()
+		.map(serde_json::to_value)
+		.transpose()?
+		.unwrap_or_default();
+
+	let used = entry.data["options"]
+		.as_array()
+		.cloned()
+		.unwrap_or_default();
+	let mut options = definition["options"]
+		.as_array()
+		.into_iter()
+		.flatten()
+		.filter_map(|option| {
+			let kind = option["type"].as_u64()?;
+			if ![STRING_OPTION, INTEGER_OPTION, BOOLEAN_OPTION, NUMBER_OPTION]
+				.contains(&kind)
+			{
+				return None;
+			}
+			let name = option["name"].as_str()?.to_string();
+			let choices = option["choices"]
+				.as_array()
+				.into_iter()
+				.flatten()
+				.filter_map(|choice| {
+					Some((
+						choice["name"].as_str()?.to_string(),
+						choice["value"].clone(),
+					))
+				})
+				.collect::<Vec<_>>();
+			let current = used
+				.iter()
+				.find(|used| used["name"] == name.as_str())
+				.map(|used| {
+					let value = &used["value"];
+					choices
+						.iter()
+						.find(|(_, choice)| choice == value)
+						.map(|(choice, _)| choice.clone())
+						.unwrap_or_else(|| match value {
+							Value::String(value) => value.clone(),
+							value => value.to_string(),
+						})
+				});
+			Some(EditableOption {
+				name,
+				kind,
+				required: option["required"].as_bool().unwrap_or(false),
+				choices,
+				current,
+			})
+		})
+		.collect::<Vec<_>>();
+
+	// Keep the ones that were used when there are too many for a modal.
+	options.sort_by_key(|option| option.current.is_none());
+	options.truncate(MAX_MODAL_INPUTS);
+	Ok(options)
+}
+
+/// Writes the modal's values into the command data. Empty values remove
+/// the option.
+fn apply_edits(
+	mut data: Value,
+	options: &[EditableOption],
+	values: &HashMap<String, String>,
+) -> Result<Value> {
+	let mut used = data["options"].as_array().cloned().unwrap_or_default();
+
+	for option in options {
+		let value = values.get(&option.name).map_or("", |value| value.trim());
+		used.retain(|used| used["name"] != option.name.as_str());
+		if value.is_empty() {
+			if option.required {
+				return Err(anyhow!("{} is required.", option.name));
+			}
+			continue;
+		}
+
+		let value = if !option.choices.is_empty() {
+			option
+				.choices
+				.iter()
+				.find(|(name, _)| name.eq_ignore_ascii_case(value))
+				.map(|(_, choice)| choice.clone())
+				.ok_or_else(|| {
+					anyhow!(
+						"{} must be one of: {}",
+						option.name,
+						option
+							.choices
+							.iter()
+							.map(|(name, _)| name.as_str())
+							.collect::<Vec<_>>()
+							.join(", ")
+					)
+				})?
+		} else {
+			match option.kind {
+				INTEGER_OPTION => {
+					Value::from(value.parse::<i64>().map_err(|_| {
+						anyhow!("{} must be a whole number.", option.name)
+					})?)
+				}
+				NUMBER_OPTION => {
+					Value::from(value.parse::<f64>().map_err(|_| {
+						anyhow!("{} must be a number.", option.name)
+					})?)
+				}
+				BOOLEAN_OPTION => Value::from(
+					value.to_lowercase().parse::<bool>().map_err(|_| {
+						anyhow!("{} must be true or false.", option.name)
+					})?,
+				),
+				_ => Value::from(value),
+			}
+		};
+		used.push(serde_json::json!({
+			"name": option.name,
+			"type": option.kind,
+			"value": value,
+		}));
+	}
+
+	data["options"] = Value::Array(used);
+	Ok(data)
+}
@@ -0,0 +1,95 @@
+use anyhow::Result;
+use poise::{
+	serenity_prelude::{CreateAllowedMentions, CreateEmbed},
+	CreateReply,
+};
+
+use crate::{
+	config::Context,
+	helpers::ContextExt,
+	jobs::{JobClass, JobInfo},
+};
+
+/// Lists the jobs that are running or waiting for a slot. Ephemeral by
+/// default.
+#[poise::command(
+	slash_command,
+	owners_only,
+	track_edits,
+	install_context = "User",
+	interaction_context = "Guild|BotDm|PrivateChannel",
+	ephemeral
+)]
+pub async fn jobs(
+	ctx: Context<'_>,
+	#[description = "Whether or not to show the message."] ephemeral: Option<
+		bool,
+	>,
+) -> Result<()> {
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(true));
+	if ephemeral {
+		ctx.defer_ephemeral().await?;
+	} else {
+		ctx.defer().await?;
+	}
+
+	let jobs = ctx.data().jobs.list();
+	let config = ctx.data().config();
+
+	let mut embed = CreateEmbed::new()
+		.title("Jobs")
+		.color(ctx.settings().embed_color);
+	if jobs.is_empty() {
+		embed = embed.description("Nothing is running.");
+	}
+	for class in [JobClass::Video, JobClass::Image, JobClass::Download] {
+		let (running, queued): (Vec<&JobInfo>, Vec<&JobInfo>) = jobs
+			.iter()
+			.filter(|job| job.class == class)
+			.partition(|job| job.started_at.is_some());
+		if running.is_empty() && queued.is_empty() {
+			continue;
+		}
+
+		let lines = running
+			.iter()
+			.map(|job| {
+				format!(
+					"Running **{}** for <@{}>, started <t:{}:R>",
+					job.command,
+					job.user_id,
+					job.started_at.unwrap_or(job.queued_at)
+				)
+			})
+			.chain(queued.iter().enumerate().map(|(index, job)| {
+				format!(
+					"#{} **{}** for <@{}>, queued <t:{}:R>",
+					index + 1,
+					job.command,
+					job.user_id,
+					job.queued_at
+				)
+			}))
+			.collect::<Vec<_>>()
+			.join("\n");
+		embed = embed.field(
+			format!(
+				"{} ({}/{} running)",
+				class.name(),
+				running.len(),
+				config.job_concurrency.limit(class)
+			),
+			lines,
+			false,
+		);
+	}
+
+	ctx.send(
+		CreateReply::default()
+			.allowed_mentions(CreateAllowedMentions::default())
+			.ephemeral(ephemeral)
+			.embed(embed),
+	)
+	.await?;
+	Ok(())
+}
@@ -0,0 +1,282 @@
+use std::time::Duration;
+
+use crate::{
+	config::{ApplicationContext, Context},
+	helpers::{
+		escape_markdown, format_bytes, format_duration,
+		AttachmentOrThumbnail, ContextExt,
+	},
+	media,
+	picker::MenuTarget,
+	probe::{MediaInfo, StreamInfo, StreamKind},
+	workspace::Workspace,
+};
+use anyhow::{anyhow, Result};
+use poise::{
+	serenity_prelude::{
+		Attachment, CreateAllowedMentions, CreateAttachment, CreateEmbed,
+		CreateEmbedFooter, Message, User,
+	},
+	CreateReply, Modal,
+};
+
+/// Discord allows 25 fields, and the file itself takes four.
+const MAX_STREAM_FIELDS: usize = 20;
+
+#[derive(Debug, Modal)]
+#[name = "Media Info"]
+struct MediaInfoModal {
+	#[placeholder = "Whether or not to show the message."]
+	ephemeral: Option<String>,
+}
+
+/// Shows what's inside a media file and whether Discord can play it.
+#[poise::command(
+	context_menu_command = "Media Info",
+	owners_only,
+	track_edits,
+	install_context = "User",
+	interaction_context = "Guild|BotDm|PrivateChannel",
+	ephemeral
+)]
+pub async fn mediainfo_context_menu(
+	ctx: ApplicationContext<'_>,
+	#[description = "The message with the media."] message: Message,
+) -> Result<()> {
+	mediainfo_menu(ctx, MenuTarget::Message(&message)).await
+}
+
+/// Shows what's inside a user's avatar or banner.
+#[poise::command(
+	context_menu_command = "Profile Media Info",
+	owners_only,
+	track_edits,
+	install_context = "User",
+	interaction_context = "Guild|BotDm|PrivateChannel",
+	ephemeral
+)]
+pub async fn mediainfo_user_context_menu(
+	ctx: ApplicationContext<'_>,
+	#[description = "The user with the avatar or banner."] user: User,
+) -> Result<()> {
+	mediainfo_menu(ctx, MenuTarget::User(&user)).await
+}
+
+/// What both Media Info menus do once they know where the file comes from.
+async fn mediainfo_menu(
+	ctx: ApplicationContext<'_>,
+	target: MenuTarget<'_>,
+) -> Result<()> {
+	let data = MediaInfoModal::execute(ctx)
+		.await?
+		.ok_or_else(|| anyhow!("No modal data."))?;
+
+	let ephemeral = match data.ephemeral.as_deref() {
+		Some("false") => false,
+		Some(_) => true,
+		None => ctx.default_ephemeral(true),
+	};
+
+	let attachment = &target.pick(ctx).await?;
+
+	let reply = media_info_reply(ctx.into(), attachment).await?;
+	ctx.send(reply.ephemeral(ephemeral)).await?;
+
+	Ok(())
+}
+
+/// Shows what's inside a media file and whether Discord can play it.
+#[poise::command(
+	slash_command,
+	owners_only,
+	track_edits,
+	install_context = "User",
+	interaction_context = "Guild|BotDm|PrivateChannel",
+	ephemeral
+)]
+pub async fn mediainfo(
+	ctx: Context<'_>,
+	#[description = "The file to look at."] attachment: Attachment,
+	#[description = "Whether or not to show the message."] ephemeral: Option<
+		bool,
+	>,
+) -> Result<()> {
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(true));
+	if ephemeral {
+		ctx.defer_ephemeral().await?;
+	} else {
+		ctx.defer().await?;
+	}
+
+	let attachment = AttachmentOrThumbnail::Attachment(attachment);
+	let reply = media_info_reply(ctx, &attachment).await?;
+	ctx.send(reply.ephemeral(ephemeral)).await?;
+
+	Ok(())
+}
+
+/// Probes the file and builds the embed, with ffprobe's JSON attached.
+async fn media_info_reply(
+	ctx: Context<'_>,
+	attachment: &AttachmentOrThumbnail,
+) -> Result<CreateReply> {
+	let filename = attachment.filename();
+	let workspace = Workspace::new("mediainfo")?;
+	let download = workspace.download(&ctx.data().http, attachment).await?;
+	let info = download.info()?;
+	let size = match info.size {
+		Some(size) => size,
+		None => std::fs::metadata(&download.path)?.len(),
+	};
+	let json = download.probe_json?;
+
+	let mut embed = CreateEmbed::new()
+		.title(truncate(escape_markdown(&filename), 256))
+		.color(ctx.settings().embed_color)
+		.field("Container", container_text(&info), true)
+		.field("Size", format_bytes(size), true)
+		.field(
+			"Duration",
+			info.duration
+				.map(format_media_duration)
+				.unwrap_or_else(|| "Unknown".to_string()),
+			true,
+		)
+		.field(
+			"Bitrate",
+			info.bit_rate
+				.map(format_bit_rate)
+				.unwrap_or_else(|| "Unknown".to_string()),
+			true,
+		);
+	for stream in info.streams.iter().take(MAX_STREAM_FIELDS) {
+		embed = embed.field(
+			format!("#{} {}", stream.index, stream_title(stream)),
+			truncate(stream_text(stream), 1024),
+			false,
+		);
+	}
+	if info.streams.len() > MAX_STREAM_FIELDS {
+		embed = embed.footer(CreateEmbedFooter::new(format!(
+			"{} more streams are in the JSON.",
+			info.streams.len() - MAX_STREAM_FIELDS
+		)));
+	}
+
+	let problems = media::inline_playback_problems(&info, &filename);
+	let playback = if problems.is_empty() {
+		"Yes".to_string()
+	} else {
+		format!(
+			"No\n{}",
+			problems
+				.iter()
+				.map(|problem| format!("- {}", problem))
+				.collect::<Vec<_>>()
+				.join("\n")
+		)
+	};
+	embed = embed.field("Plays inline", truncate(playback, 1024), false);
+
+	Ok(CreateReply::default()
+		.allowed_mentions(CreateAllowedMentions::default())
+		.embed(embed)
+		.attachment(CreateAttachment::bytes(
+			json,
+			format!("{}.json", filename),
+		)))
+}
+
+fn container_text(info: &MediaInfo) -> String {
+	match &info.container_long_name {
+		Some(long_name) => format!("{} (`{}`)", long_name, info.container),
+		None => format!("`{}`", info.container),
+	}
+}
+
+fn stream_title(stream: &StreamInfo) -> &'static str {
+	match stream.kind {
+		StreamKind::Video if stream.attached_pic => "Cover Art",
+		StreamKind::Video => "Video",
+		StreamKind::Audio => "Audio",
+		StreamKind::Subtitle => "Subtitles",
+		StreamKind::Data => "Data",
+		StreamKind::Attachment => "Attachment",
+		StreamKind::Unknown => "Unknown",
+	}
+}
+
+/// One stream's details, such as its codec, size, frame rate, audio layout
+/// and bitrate, in the order ffprobe lists them.
+fn stream_text(stream: &StreamInfo) -> String {
+	let codec = match (&stream.codec_long_name, &stream.codec) {
+		(Some(long_name), Some(codec)) => {
+			format!("{} (`{}`)", long_name, codec)
+		}
+		(None, Some(codec)) => format!("`{}`", codec),
+		_ => "Unknown codec".to_string(),
+	};
+	let mut parts = vec![match &stream.profile {
+		Some(profile) => format!("{}, {} profile", codec, profile),
+		None => codec,
+	}];
+
+	if let (Some(width), Some(height)) = (stream.width, stream.height) {
+		parts.push(format!("{}×{}", width, height));
+	}
+	if let Some(rotation) = stream.rotation.filter(|rotation| *rotation != 0)
+	{
+		parts.push(format!("rotated {}°", rotation));
+	}
+	if stream.kind == StreamKind::Video && !stream.attached_pic {
+		if let Some(frame_rate) = stream.frame_rate {
+			parts.push(format!("{} fps", format_frame_rate(frame_rate)));
+		}
+	}
+	if let Some(pixel_format) = &stream.pixel_format {
+		parts.push(pixel_format.clone());
+	}
+	if let Some(sample_rate) = stream.sample_rate {
+		parts.push(format!("{} Hz", sample_rate));
+	}
+	match (&stream.channel_layout, stream.channels) {
+		(Some(layout), _) => parts.push(layout.clone()),
+		(None, Some(channels)) => {
+			parts.push(format!("{} channels", channels))
+		}
+		_ => {}
+	}
+	if let Some(bit_rate) = stream.bit_rate {
+		parts.push(format_bit_rate(bit_rate));
+	}
+	parts.join(", ")
+}
+
+fn format_bit_rate(bit_rate: u64) -> String {
+	format!("{} kb/s", bit_rate / 1000)
+}
+
+/// Such as `29.97` or `30`.
+fn format_frame_rate(frame_rate: f64) -> String {
+	format!("{:.3}", frame_rate)
+		.trim_end_matches('0')
+		.trim_end_matches('.')
+		.to_string()
+}
+
+/// Short clips get fractions of a second, since `0s` isn't much help.
+fn format_media_duration(duration: Duration) -> String {
+	if duration.as_secs() < 60 {
+		format!("{:.2}s", duration.as_secs_f64())
+	} else {
+		format_duration(duration)
+	}
+}
+
+fn truncate(mut text: String, max_length: usize) -> String {
+	if text.chars().count() > max_length {
+		text = text.chars().take(max_length - 1).collect();
+		text.push('…');
+	}
+	text
+}
@@ -1,6 +1,9 @@
 #![allow(clippy::too_many_arguments)]
 
-use crate::crunch;
+use crate::{
+	config::{BotData, Config},
+	crunch,
+};
 
 crunch![
 	age,
@@ -20,11 +23,114 @@ crunch![
 	ffmpeg,
 	translate,
 	help,
-	webp,
-	jxl,
+	convert,
 	embed,
 	screenshot,
 	flip,
 	now_playing,
 	song_info,
+	register,
+	settings,
+	history,
+	jobs,
+	mediainfo,
+	playable,
+	compress,
 ];
+
+pub type Command = poise::Command<BotData, anyhow::Error>;
+
+/// Every command the bot has, in the order they're registered.
+pub fn all_commands() -> Vec<Command> {
+	vec![
+		age(),
+		github(),
+		fix(),
+		uptime(),
+		help(),
+		snowstamp(),
+		wolfram(),
+		wayback(),
+		unicode(),
+		escape(),
+		roll(),
+		youtube(),
+		ocr(),
+		bible(),
+		favoritize(),
+		favoritize_context_menu(),
+		favoritize_user_context_menu(),
+		convert(),
+		convert_context_menu(),
+		convert_user_context_menu(),
+		ffmpeg(),
+		translate(),
+		translate_context_menu(),
+		embed(),
+		screenshot(),
+		flip(),
+		now_playing(),
+		song_info(),
+		register(),
+		settings(),
+		history(),
+		jobs(),
+		mediainfo(),
+		mediainfo_context_menu(),
+		mediainfo_user_context_menu(),
+		make_playable_context_menu(),
+		compress(),
+		compress_context_menu(),
+		compress_user_context_menu(),
+	]
+}
+
+/// The commands that are enabled in the config and have everything they
+/// need from it.
+pub fn enabled_commands(config: &Config) -> Vec<Command> {
+	let commands = all_commands();
+
+	for name in config.commands.keys() {
+		if !commands.iter().any(|c| &c.identifying_name == name) {
+			tracing::warn!("Unknown command in config: {}", name);
+		}
+	}
+
+	commands
+		.into_iter()
+		.filter(|command| {
+			let name = &command.identifying_name;
+			if !config.command_enabled(name) {
+				return false;
+			}
+			if let Some(field) = missing_requirement(config, name) {
+				tracing::info!(
+					"Skipping {} because {} isn't set.",
+					name,
+					field
+				);
+				return false;
+			}
+			true
+		})
+		.collect()
+}
+
+/// The config field a command can't run without, if it's missing.
+fn missing_requirement(config: &Config, name: &str) -> Option<&'static str> {
+	let is_set = |value: &Option<String>| {
+		value
+			.as_deref()
+			.is_some_and(|value| !value.trim().is_empty())
+	};
+
+	match name {
+		"flip" if !is_set(&config.randomorg_api_key) => {
+			Some("randomorg_api_key")
+		}
+		"wolfram" if !is_set(&config.wolfram_alpha_full_app_id) => {
+			Some("wolfram_alpha_full_app_id")
+		}
+		_ => None,
+	}
+}
@@ -4,7 +4,11 @@ use inline_format::format;
 use serde::Deserialize;
 use url::Url;
 
-use crate::{commands::build_song_info_message, config::Context};
+use crate::{
+	commands::build_song_info_message,
+	config::Context,
+	helpers::{ContextExt, RequestBuilderExt},
+};
 
 #[derive(Debug, Clone)]
 pub struct PlayingNow {
@@ -21,12 +25,21 @@ impl<'de> Deserialize<'de> for PlayingNow {
 	where
 		D: serde::Deserializer<'de>,
 	{
+		use serde::de::Error;
+
 		let value =
 			serde_json::Value::deserialize(deserializer)?["payload"].clone();
 
 		Ok(Self {
-			user_id: value["user_id"].as_str().unwrap().to_string(),
-			playing_now: value["playing_now"].as_bool().unwrap(),
+			user_id: value["user_id"]
+				.as_str()
+				.ok_or_else(|| {
+					D::Error::custom("ListenBrainz sent no user_id.")
+				})?
+				.to_string(),
+			playing_now: value["playing_now"].as_bool().ok_or_else(|| {
+				D::Error::custom("ListenBrainz sent no playing_now.")
+			})?,
 			duration: value["listens"][0]["track_metadata"]
 				["additional_info"]["duration"]
 				.as_u64()
@@ -63,7 +76,7 @@ pub async fn now_playing(
 		bool,
 	>,
 ) -> Result<()> {
-	let ephemeral = ephemeral.unwrap_or(false);
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(false));
 	if ephemeral {
 		ctx.defer_ephemeral().await?;
 	} else {
@@ -77,14 +90,14 @@ pub async fn now_playing(
 			"https://api.listenbrainz.org/1/user/",
 			&urlencoding::encode(
 				&user.or(
-					ctx.data().config.listenbrainz_user.clone()
+					ctx.settings().listenbrainz_user
 				).ok_or(
-					anyhow!("No user was provided and no default user is set in the config.")
+					anyhow!("No user was provided and no default user is set in your settings or the config.")
 				)?
 			),
 			"/playing-now"
 		))
-		.send()
+		.send_measured()
 		.await?
 		.json::<PlayingNow>()
 		.await?;
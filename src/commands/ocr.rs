@@ -9,7 +9,10 @@ use poise::{
 };
 use rusty_tesseract::Image;
 
-use crate::{config::Context, helpers::CreateReplyExt};
+use crate::{
+	config::Context,
+	helpers::{ContextExt, CreateReplyExt},
+};
 
 pub async fn run_ocr(image: Attachment, languages: String) -> Result<String> {
 	let image = image.download().await?;
@@ -62,7 +65,7 @@ pub async fn ocr(
 	>,
 ) -> Result<()> {
 	let languages = languages.unwrap_or("eng".to_string());
-	let ephemeral = ephemeral.unwrap_or(false);
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(false));
 	if ephemeral {
 		ctx.defer_ephemeral().await?;
 	} else {
@@ -0,0 +1,81 @@
+use std::fs;
+
+use crate::{
+	config::ApplicationContext,
+	helpers::{change_extension, AttachmentOrThumbnail, ContextExt},
+	jobs::{self, JobClass},
+	media,
+	picker::pick_attachment,
+	workspace::Workspace,
+};
+use anyhow::{anyhow, Result};
+use poise::{
+	serenity_prelude::{CreateAllowedMentions, CreateAttachment, Message},
+	CreateReply, Modal,
+};
+
+#[derive(Debug, Modal)]
+#[name = "Make Playable"]
+struct PlayableModal {
+	#[placeholder = "Whether or not to show the message."]
+	ephemeral: Option<String>,
+}
+
+/// Remuxes or re-encodes a video so Discord plays it inline.
+#[poise::command(
+	context_menu_command = "Make Playable",
+	owners_only,
+	track_edits,
+	install_context = "User",
+	interaction_context = "Guild|BotDm|PrivateChannel",
+	ephemeral
+)]
+pub async fn make_playable_context_menu(
+	ctx: ApplicationContext<'_>,
+	#[description = "The message with the video."] message: Message,
+) -> Result<()> {
+	let data = PlayableModal::execute(ctx)
+		.await?
+		.ok_or_else(|| anyhow!("No modal data."))?;
+
+	let ephemeral = match data.ephemeral.as_deref() {
+		Some("false") => false,
+		Some(_) => true,
+		None => ctx.default_ephemeral(false),
+	};
+
+	let attachment = &pick_attachment(ctx, &message).await?;
+
+	let mut reply = CreateReply::default()
+		.allowed_mentions(CreateAllowedMentions::default())
+		.ephemeral(ephemeral);
+
+	let new_video_data = jobs::run(
+		ctx.into(),
+		JobClass::Video,
+		ephemeral,
+		convert_to_playable(&ctx.data().http, attachment),
+	)
+	.await?;
+	let new_video_name = change_extension(attachment.filename(), "mp4")
+		.to_string_lossy()
+		.to_string();
+	reply = reply
+		.attachment(CreateAttachment::bytes(new_video_data, new_video_name));
+
+	ctx.send(reply).await?;
+
+	Ok(())
+}
+
+async fn convert_to_playable(
+	client: &reqwest::Client,
+	attachment: &AttachmentOrThumbnail,
+) -> Result<Vec<u8>> {
+	let workspace = Workspace::new("playable")?;
+	let download = workspace.download(client, attachment).await?;
+	let info = download.info()?;
+	let output_path =
+		media::make_playable(&download.path, &info, &workspace).await?;
+	Ok(fs::read(output_path)?)
+}
@@ -0,0 +1,127 @@
+use anyhow::{anyhow, Result};
+use poise::{
+	serenity_prelude::{CommandType, CreateAllowedMentions},
+	ChoiceParameter, CreateReply,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	config::Context,
+	helpers::{ContextExt, CreateReplyExt},
+	registration::{self, Scope},
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone, ChoiceParameter)]
+pub enum RegisterAction {
+	Sync,
+	Clear,
+	List,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ChoiceParameter)]
+pub enum RegisterScope {
+	Global,
+	Guild,
+}
+
+/// Manages the commands registered with Discord. Ephemeral by default.
+///
+/// Sync only pushes when the commands changed. The guild scope uses
+/// `dev_guild_id` from the config, or the current guild if it isn't set.
+#[poise::command(
+	slash_command,
+	owners_only,
+	track_edits,
+	install_context = "User",
+	interaction_context = "Guild|BotDm|PrivateChannel",
+	ephemeral
+)]
+pub async fn register(
+	ctx: Context<'_>,
+	#[description = "What to do with the registered commands."]
+	action: RegisterAction,
+	#[description = "Where the commands are registered. (default: config)"]
+	scope: Option<RegisterScope>,
+	#[description = "Whether or not to show the message."] ephemeral: Option<
+		bool,
+	>,
+) -> Result<()> {
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(true));
+	if ephemeral {
+		ctx.defer_ephemeral().await?;
+	} else {
+		ctx.defer().await?;
+	}
+
+	let config = ctx.data().config();
+	let scope =
+		match scope {
+			None => Scope::from_config(&config),
+			Some(RegisterScope::Global) => Scope::Global,
+			Some(RegisterScope::Guild) => Scope::Guild(
+				config.dev_guild_id.or(ctx.guild_id()).ok_or_else(|| {
+					anyhow!("There's no `dev_guild_id` set and this isn't a guild.")
+				})?,
+			),
+		};
+
+	let mut reply = CreateReply::default()
+		.allowed_mentions(CreateAllowedMentions::default())
+		.ephemeral(ephemeral);
+
+	let http = &ctx.serenity_context().http;
+	match action {
+		RegisterAction::Sync => {
+			let commands = &ctx.framework().options().commands;
+			reply = if registration::sync(http, commands, scope).await? {
+				reply.content(format!("Registered commands {}.", scope))
+			} else {
+				reply.content(format!(
+					"Commands are already up to date {}.",
+					scope
+				))
+			};
+		}
+		RegisterAction::Clear => {
+			registration::clear(http, scope).await?;
+			reply = reply.content(format!("Cleared commands {}.", scope));
+		}
+		RegisterAction::List => {
+			let registered = registration::list(http, scope).await?;
+			let lines = registered
+				.iter()
+				.map(|command| {
+					let name = match command.kind {
+						CommandType::ChatInput => {
+							format!("/{}", command.name)
+						}
+						CommandType::User => {
+							format!("User > {}", command.name)
+						}
+						CommandType::Message => {
+							format!("Message > {}", command.name)
+						}
+						_ => command.name.clone(),
+					};
+					format!("{} ({})", name, command.id)
+				})
+				.collect::<Vec<_>>();
+			reply = reply.content_or_attachment(|is_content| {
+				let header = format!(
+					"{} command{} registered {}.",
+					lines.len(),
+					if lines.len() == 1 { "" } else { "s" },
+					scope
+				);
+				if is_content {
+					format!("{}\n```\n{}\n```", header, lines.join("\n"))
+				} else {
+					format!("{}\n{}", header, lines.join("\n"))
+				}
+			});
+		}
+	}
+
+	ctx.send(reply).await?;
+	Ok(())
+}
@@ -5,7 +5,7 @@ use poise::{
 	CreateReply,
 };
 
-use crate::config::Context;
+use crate::{config::Context, helpers::ContextExt};
 
 /// Rolls dice notation.
 ///
@@ -57,7 +57,7 @@ pub async fn roll(
 		bool,
 	>,
 ) -> Result<()> {
-	let ephemeral = ephemeral.unwrap_or(false);
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(false));
 	if ephemeral {
 		ctx.defer_ephemeral().await?;
 	} else {
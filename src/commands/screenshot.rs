@@ -1,9 +1,8 @@
 use thirtyfour::prelude::*;
 use url::Url;
 
-use crate::config::Context;
+use crate::{config::Context, helpers::ContextExt};
 use anyhow::Result;
-use inline_format::println;
 use poise::{
 	serenity_prelude::{
 		CreateActionRow, CreateAllowedMentions, CreateAttachment,
@@ -30,7 +29,7 @@ pub async fn screenshot(
 		bool,
 	>,
 ) -> Result<()> {
-	let ephemeral = ephemeral.unwrap_or(false);
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(false));
 	if ephemeral {
 		ctx.defer_ephemeral().await?;
 	} else {
@@ -55,7 +54,7 @@ pub async fn screenshot(
 }
 
 pub async fn screenshot_url(url: &Url) -> Result<Vec<u8>> {
-	println!("Starting Firefox.");
+	tracing::debug!("Starting Firefox.");
 
 	let mut caps = DesiredCapabilities::firefox();
 	// If in debug mode, run non-headless.
@@ -0,0 +1,146 @@
+use anyhow::{anyhow, Result};
+use poise::{
+	serenity_prelude::{CreateAllowedMentions, CreateEmbed},
+	ChoiceParameter, CreateReply,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	config::Context,
+	helpers::ContextExt,
+	profile::{Profile, ProfileField, Settings},
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone, ChoiceParameter)]
+pub enum SettingsAction {
+	View,
+	Set,
+	Reset,
+}
+
+/// Shows or changes your personal settings. Ephemeral by default.
+///
+/// Anything you haven't set uses the value from the config. Reset without
+/// a setting clears all of them.
+#[poise::command(
+	slash_command,
+	owners_only,
+	track_edits,
+	install_context = "User",
+	interaction_context = "Guild|BotDm|PrivateChannel",
+	ephemeral
+)]
+pub async fn settings(
+	ctx: Context<'_>,
+	#[description = "What to do with your settings."] action: SettingsAction,
+	#[description = "The setting to set or reset."] setting: Option<
+		ProfileField,
+	>,
+	#[description = "The new value for the setting."] value: Option<String>,
+	#[description = "Whether or not to show the message."] ephemeral: Option<
+		bool,
+	>,
+) -> Result<()> {
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(true));
+	if ephemeral {
+		ctx.defer_ephemeral().await?;
+	} else {
+		ctx.defer().await?;
+	}
+
+	let user_id = ctx.author().id;
+	let mut profile = ctx.data().profile(user_id);
+
+	let content = match action {
+		SettingsAction::View => None,
+		SettingsAction::Set => {
+			let setting = setting
+				.ok_or_else(|| anyhow!("Choose the setting to change."))?;
+			let value = value.ok_or_else(|| {
+				anyhow!("Give a value for {}.", setting.name())
+			})?;
+			profile.set(&setting, &value)?;
+			ctx.data().save_profile(user_id, profile.clone()).await?;
+			Some(format!("Set {}.", setting.name()))
+		}
+		SettingsAction::Reset => {
+			let content = match &setting {
+				Some(setting) => {
+					profile.reset(setting);
+					format!("Reset {} to the config's value.", setting.name())
+				}
+				None => {
+					profile = Profile::default();
+					"Reset all of your settings to the config's values."
+						.to_string()
+				}
+			};
+			ctx.data().save_profile(user_id, profile.clone()).await?;
+			Some(content)
+		}
+	};
+
+	let mut reply = CreateReply::default()
+		.allowed_mentions(CreateAllowedMentions::default())
+		.ephemeral(ephemeral)
+		.embed(settings_embed(&ctx.settings(), &profile));
+	if let Some(content) = content {
+		reply = reply.content(content);
+	}
+
+	ctx.send(reply).await?;
+	Ok(())
+}
+
+fn settings_embed(settings: &Settings, profile: &Profile) -> CreateEmbed {
+	// Marks the values that come from the config instead of the profile.
+	let source = |is_set: bool| if is_set { "" } else { " (config)" };
+
+	CreateEmbed::new()
+		.title("Settings")
+		.color(settings.embed_color.clone())
+		.field(
+			ProfileField::ListenBrainzUser.name(),
+			format!(
+				"{}{}",
+				settings.listenbrainz_user.as_deref().unwrap_or("None"),
+				source(profile.listenbrainz_user.is_some())
+			),
+			true,
+		)
+		.field(
+			ProfileField::DeepLTargetLanguage.name(),
+			format!(
+				"{}{}",
+				settings.deepl_target_language,
+				source(profile.deepl_target_language.is_some())
+			),
+			true,
+		)
+		.field(
+			ProfileField::Timezone.name(),
+			format!(
+				"{}{}",
+				settings.timezone.name(),
+				source(profile.timezone.is_some())
+			),
+			true,
+		)
+		.field(
+			ProfileField::Ephemeral.name(),
+			match settings.ephemeral {
+				Some(ephemeral) => ephemeral.to_string(),
+				None => "Per command".to_string(),
+			},
+			true,
+		)
+		.field(
+			ProfileField::EmbedColor.name(),
+			format!(
+				"#{:06X}{}",
+				settings.embed_color.0,
+				source(profile.embed_color.is_some())
+			),
+			true,
+		)
+}
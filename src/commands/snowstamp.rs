@@ -1,5 +1,6 @@
 use anyhow::{anyhow, Result};
-use chrono::{Datelike, Local, Timelike};
+use chrono::{Datelike, Timelike, Utc};
+use chrono_tz::Tz;
 use poise::{
 	serenity_prelude::{
 		self as serenity, async_trait, CreateAllowedMentions,
@@ -8,7 +9,7 @@ use poise::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::config::Context;
+use crate::{config::Context, helpers::ContextExt};
 
 #[derive(Debug, Serialize, Deserialize, Clone, ChoiceParameter)]
 enum Month {
@@ -105,8 +106,8 @@ struct InputTime {
 	second: Option<u32>,
 }
 impl InputTime {
-	fn now() -> Self {
-		let now = Local::now();
+	fn now(timezone: Tz) -> Self {
+		let now = Utc::now().with_timezone(&timezone);
 		Self {
 			year: Some(now.year()),
 			month: Some(now.month()),
@@ -116,9 +117,9 @@ impl InputTime {
 			second: Some(now.second()),
 		}
 	}
-	fn to_timestamp(&self) -> Result<i64> {
+	fn to_timestamp(&self, timezone: Tz) -> Result<i64> {
 		// Get the current time
-		let mut datetime = Local::now();
+		let mut datetime = Utc::now().with_timezone(&timezone);
 
 		// Determine year
 		datetime = datetime
@@ -224,7 +225,7 @@ pub async fn snowstamp(
 		bool,
 	>,
 ) -> Result<()> {
-	let ephemeral = ephemeral.unwrap_or(false);
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(false));
 	if ephemeral {
 		ctx.defer_ephemeral().await?;
 	} else {
@@ -239,10 +240,12 @@ pub async fn snowstamp(
 		.allowed_mentions(CreateAllowedMentions::default())
 		.ephemeral(ephemeral);
 
+	// Dates and times are in the user's timezone.
+	let timezone = ctx.settings().timezone;
 	let id_or_time = match (id, year, month, day, hour, minute, second) {
 		(Some(id), None, None, None, None, None, None) => IdOrTime::Id(id),
 		(None, None, None, None, None, None, None) => {
-			IdOrTime::Time(InputTime::now().to_timestamp()?)
+			IdOrTime::Time(InputTime::now(timezone).to_timestamp(timezone)?)
 		}
 		(None, year, month, day, hour, minute, second) => IdOrTime::Time(
 			InputTime {
@@ -253,7 +256,7 @@ pub async fn snowstamp(
 				minute,
 				second,
 			}
-			.to_timestamp()?,
+			.to_timestamp(timezone)?,
 		),
 		_ => IdOrTime::None,
 	};
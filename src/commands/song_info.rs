@@ -16,7 +16,7 @@ use url::Url;
 
 use crate::{
 	config::{Color, Context},
-	helpers::escape_markdown,
+	helpers::{escape_markdown, ContextExt, RequestBuilderExt},
 };
 
 static PLATFORM_CAPITALIZATIONS: phf::Map<&'static str, &'static str> = phf_map! {
@@ -49,7 +49,7 @@ pub async fn get_song_platform_data(
 			"https://api.song.link/v1-alpha.1/links?url=",
 			encoded
 		))
-		.send()
+		.send_measured()
 		.await?
 		.json::<serde_json::Value>()
 		.await?;
@@ -90,10 +90,8 @@ pub async fn get_song_platform_data(
 				.map(|s| s.to_string()),
 			thumbnail_quality: entity["thumbnailWidth"]
 				.as_u64()
-				.map(|w| {
-					(w * entity["thumbnailHeight"].as_u64().unwrap()).to_u32()
-				})
-				.flatten(),
+				.zip(entity["thumbnailHeight"].as_u64())
+				.and_then(|(w, h)| (w * h).to_u32()),
 		});
 	}
 
@@ -161,7 +159,7 @@ pub async fn build_song_info_message(
 		.filter_map(|platform| platform.thumbnail_url.as_ref());
 	let mut thumbnail_url_bytes: Option<_> = None;
 	for url in thumbnail_urls {
-		if let Ok(res) = ctx.data().http.get(url).send().await {
+		if let Ok(res) = ctx.data().http.get(url).send_measured().await {
 			if let Ok(bytes) = res.bytes().await {
 				// Ensure the server actually responded.
 				// artwork.anghcdn.co loves ignoring requests.
@@ -180,13 +178,13 @@ pub async fn build_song_info_message(
 	}
 
 	let embed_color = {
-		if let Some(thumbnail_url_bytes) = thumbnail_url_bytes {
+		// Fall back to the default color if the thumbnail can't be decoded.
+		let thumbnail_image = thumbnail_url_bytes
+			.and_then(|bytes| image::load_from_memory(&bytes).ok());
+		if let Some(thumbnail_image) = thumbnail_image {
 			use color_thief::{get_palette, ColorFormat};
 
-			let color_bytes = image::load_from_memory(&thumbnail_url_bytes)
-				.unwrap()
-				.to_rgb8()
-				.into_raw();
+			let color_bytes = thumbnail_image.to_rgb8().into_raw();
 
 			get_palette(&color_bytes[..], ColorFormat::Rgb, 10, 2)?
 				.iter()
@@ -199,9 +197,9 @@ pub async fn build_song_info_message(
 							| (color.b as u32) << 16,
 					)
 				})
-				.unwrap_or(ctx.data().config.embed_color.clone())
+				.unwrap_or(ctx.settings().embed_color)
 		} else {
-			ctx.data().config.embed_color.clone()
+			ctx.settings().embed_color
 		}
 	};
 	reply = reply.embed(
@@ -246,7 +244,7 @@ pub async fn song_info(
 		bool,
 	>,
 ) -> Result<()> {
-	let ephemeral = ephemeral.unwrap_or(false);
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(false));
 	if ephemeral {
 		ctx.defer_ephemeral().await?;
 	} else {
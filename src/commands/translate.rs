@@ -3,12 +3,13 @@ use thirtyfour::prelude::*;
 use url::Url;
 
 use crate::{
-	helpers::{wait_for_element, CreateReplyExt},
 	config::{ApplicationContext, Context},
+	helpers::{wait_for_element, ContextExt, CreateReplyExt},
+	logging,
 };
 use anyhow::{anyhow, Result};
 use fancy_regex::Regex;
-use inline_format::{format, println};
+use inline_format::format;
 use poise::{
 	serenity_prelude::{
 		CreateActionRow, CreateAllowedMentions, CreateButton, Message,
@@ -150,7 +151,7 @@ pub async fn translate_context_menu(
 	let ephemeral = match data.ephemeral.as_deref() {
 		Some("false") => false,
 		Some(_) => true,
-		None => false,
+		None => ctx.default_ephemeral(false),
 	};
 
 	let mut reply = CreateReply::default()
@@ -162,7 +163,7 @@ pub async fn translate_context_menu(
 		&data.source_language.unwrap_or("auto".to_string()),
 		&data
 			.target_language
-			.unwrap_or(ctx.data().config.deepl_target_language.clone()),
+			.unwrap_or_else(|| ctx.settings().deepl_target_language),
 	)
 	.await?;
 
@@ -198,7 +199,7 @@ pub async fn translate(
 		bool,
 	>,
 ) -> Result<()> {
-	let ephemeral = ephemeral.unwrap_or(false);
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(false));
 	if ephemeral {
 		ctx.defer_ephemeral().await?;
 	} else {
@@ -213,7 +214,7 @@ pub async fn translate(
 		&text,
 		&source_language.unwrap_or("auto".to_string()),
 		&target_language
-			.unwrap_or(ctx.data().config.deepl_target_language.clone()),
+			.unwrap_or_else(|| ctx.settings().deepl_target_language),
 	)
 	.await?;
 
@@ -242,7 +243,7 @@ pub async fn translate_text(
 	source_language: &String,
 	target_language: &String,
 ) -> Result<TranslationResult> {
-	println!("Starting Firefox.");
+	tracing::debug!("Starting Firefox.");
 
 	let mut caps = DesiredCapabilities::firefox();
 	// If in debug mode, run non-headless.
@@ -262,7 +263,7 @@ pub async fn translate_text(
 		wait_for_element(&driver, "[data-testid='glossary-button']").await?;
 	glossary_button.wait_until().clickable().await?;
 
-	println!("Page loaded.");
+	tracing::debug!("Page loaded.");
 
 	let source_element = wait_for_element(
 		&driver,
@@ -334,7 +335,6 @@ pub async fn translate_text(
 	let translated_text = target_element.text().await?.trim().to_string();
 
 	// Get selected language.
-	println!(starting_url);
 	while driver.current_url().await?.as_str() == starting_url {
 		tokio::time::sleep(Duration::from_millis(10)).await;
 	}
@@ -343,7 +343,7 @@ pub async fn translate_text(
 	url.set_path(
 		&url.path().split('/').skip(2).collect::<Vec<_>>().join("/"),
 	);
-	println!("URL: ", url:?);
+	tracing::debug!("URL: {}", logging::redact(url.as_str()));
 
 	let url_regex = Regex::new(
 		r"https:\/\/www\.deepl\.com\/translator(?:#(?<source>.+))?\/(?<target>.+)\/.*",
@@ -386,10 +386,13 @@ pub async fn translate_text(
 		.unwrap_or("auto".to_string())
 		.to_lowercase();
 
-	println!("Tried source language:   ", attempted_source_language);
-	println!("Tried target language:   ", attempted_target_language);
-	println!("Current source language: ", current_source_language);
-	println!("Current target language: ", current_target_language);
+	tracing::debug!(
+		attempted_source_language,
+		attempted_target_language,
+		current_source_language,
+		current_target_language,
+		"Checked the selected languages."
+	);
 
 	if attempted_target_language != current_target_language {
 		// Include the original language and the translation language and the ones it used.
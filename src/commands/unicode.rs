@@ -1,7 +1,7 @@
 use anyhow::Result;
 use poise::{serenity_prelude::CreateAllowedMentions, CreateReply};
 
-use crate::config::Context;
+use crate::{config::Context, helpers::ContextExt};
 
 /// Converts text to and from Unicode.
 #[poise::command(
@@ -24,7 +24,7 @@ pub async fn unicode(
 		bool,
 	>,
 ) -> Result<()> {
-	let ephemeral = ephemeral.unwrap_or(false);
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(false));
 	if ephemeral {
 		ctx.defer_ephemeral().await?;
 	} else {
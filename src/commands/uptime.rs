@@ -1,6 +1,6 @@
 use anyhow::Error;
 
-use crate::config::Context;
+use crate::{config::Context, helpers::ContextExt};
 
 /// Tells you how long the bot has been up for.
 #[poise::command(
@@ -17,7 +17,7 @@ pub async fn uptime(
 		bool,
 	>,
 ) -> Result<(), Error> {
-	let ephemeral = ephemeral.unwrap_or(false);
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(false));
 	if ephemeral {
 		ctx.defer_ephemeral().await?;
 	} else {
@@ -4,7 +4,7 @@ use poise::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::config::Context;
+use crate::{config::Context, helpers::ContextExt};
 
 #[derive(Debug, Serialize, Deserialize, Clone, ChoiceParameter)]
 pub enum WaybackAction {
@@ -34,7 +34,7 @@ pub async fn wayback(
 		bool,
 	>,
 ) -> Result<()> {
-	let ephemeral = ephemeral.unwrap_or(false);
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(false));
 	if ephemeral {
 		ctx.defer_ephemeral().await?;
 	} else {
@@ -15,7 +15,10 @@ use poise::{
 
 const EMBED_COLOR: u32 = 0xff6600;
 
-use crate::config::Context;
+use crate::{
+	config::Context,
+	helpers::{ContextExt, RequestBuilderExt},
+};
 
 pub fn generate_timeouts(time: Duration) -> String {
 	format!(
@@ -55,7 +58,7 @@ pub async fn wolfram(
 		bool,
 	>,
 ) -> Result<()> {
-	let ephemeral = ephemeral.unwrap_or(false);
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(false));
 	if ephemeral {
 		ctx.defer_ephemeral().await?;
 	} else {
@@ -63,7 +66,7 @@ pub async fn wolfram(
 	}
 
 	let wolfram_alpha_full_app_id = if let Some(app_id) =
-		ctx.data().config.wolfram_alpha_full_app_id.clone()
+		ctx.data().config().wolfram_alpha_full_app_id.clone()
 	{
 		app_id
 	} else {
@@ -80,8 +83,12 @@ pub async fn wolfram(
 	   wolfram_alpha_full_app_id
 	);
 
-	let full_response =
-		ctx.data().http.get(full_results_api_url).send().await?;
+	let full_response = ctx
+		.data()
+		.http
+		.get(full_results_api_url)
+		.send_measured()
+		.await?;
 
 	if !full_response.status().is_success() {
 		return Err(anyhow!(
@@ -194,7 +201,7 @@ pub async fn wolfram(
 					ctx.data()
 						.http
 						.get(image_url)
-						.send()
+						.send_measured()
 						.await?
 						.bytes()
 						.await?,
@@ -252,14 +259,11 @@ pub async fn wolfram(
 		}
 	}
 
-	let message = ctx.send(reply.clone()).await?;
+	let message = ctx.send(reply.clone()).await?.into_message().await?;
 
 	let mut responded_publicly = false;
 
 	'interaction_loop: while match message
-		.message()
-		.await
-		.unwrap()
 		.await_component_interaction(&ctx.serenity_context().shard)
 		.timeout(Duration::from_secs(60 * 5))
 		.await
@@ -1,12 +1,17 @@
-use std::{env, fs, process};
+use std::{fs, process};
 
-use crate::{os_command::run_os_command, config::Context};
+use crate::{
+	config::Context,
+	helpers::ContextExt,
+	jobs::{self, JobClass},
+	os_command::run_os_command,
+	workspace::Workspace,
+};
 use anyhow::{anyhow, Result};
 use poise::{
 	serenity_prelude::{CreateAllowedMentions, CreateAttachment},
 	CreateReply,
 };
-use rand::Rng;
 use url::Url;
 
 const FORMAT: &str = "mp4";
@@ -27,7 +32,7 @@ pub async fn youtube(
 		bool,
 	>,
 ) -> Result<()> {
-	let ephemeral = ephemeral.unwrap_or(false);
+	let ephemeral = ephemeral.unwrap_or_else(|| ctx.default_ephemeral(false));
 	if ephemeral {
 		ctx.defer_ephemeral().await?;
 	} else {
@@ -38,15 +43,12 @@ pub async fn youtube(
 		.allowed_mentions(CreateAllowedMentions::default())
 		.ephemeral(ephemeral);
 
-	// Generate a random filename.
-	let yt_dlp_output_path_template = &env::temp_dir()
-		.join(rand::thread_rng().gen::<u64>().to_string())
-		.to_string_lossy()
-		.to_string();
+	let workspace = Workspace::new("youtube")?;
+	let yt_dlp_output_path_template =
+		&workspace.output_path("video").to_string_lossy().to_string();
 	let yt_dlp_output_final_path =
 		&format!("{yt_dlp_output_path_template}.{FORMAT}");
 
-	println!("{:?}", yt_dlp_output_path_template);
 	let mut yt_dlp_command = process::Command::new("yt-dlp");
 	yt_dlp_command.args([
 		url.as_str(),
@@ -55,11 +57,16 @@ pub async fn youtube(
 		"--recode-video",
 		FORMAT,
 	]);
-	let yt_dlp_output = run_os_command("yt-dlp", yt_dlp_command)?;
+	let yt_dlp_output = jobs::run(
+		ctx,
+		JobClass::Download,
+		ephemeral,
+		run_os_command("yt-dlp", yt_dlp_command),
+	)
+	.await?;
 
 	if yt_dlp_output.status.success() {
 		// Read the file.
-		println!("{}", yt_dlp_output_final_path);
 		let data = fs::read(yt_dlp_output_final_path)?;
 		// Attach the file.
 		reply = reply.attachment(CreateAttachment::bytes(
@@ -67,11 +74,6 @@ pub async fn youtube(
 			format!("video.{FORMAT}"),
 		));
 	} else {
-		// Delete the file.
-		if fs::exists(yt_dlp_output_final_path)? {
-			fs::remove_file(yt_dlp_output_final_path)?;
-		}
-
 		return Err(anyhow!(
 			"```\n{}\n```",
 			String::from_utf8_lossy(&yt_dlp_output.stderr)
@@ -80,10 +82,5 @@ pub async fn youtube(
 
 	ctx.send(reply).await?;
 
-	// Delete the file.
-	if fs::exists(yt_dlp_output_final_path)? {
-		fs::remove_file(yt_dlp_output_final_path)?;
-	}
-
 	Ok(())
 }
@@ -1,7 +1,52 @@
-use anyhow::Error;
-use poise::serenity_prelude::{self as serenity, Colour};
+use anyhow::{anyhow, Context as _, Error, Result};
+use poise::serenity_prelude::{self as serenity, Colour, CreateMessage};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashSet, fs};
+use std::{
+	collections::{HashMap, HashSet},
+	env, fs,
+	net::SocketAddr,
+	num::NonZeroU64,
+	path::{Path, PathBuf},
+	str::FromStr,
+	sync::{Arc, RwLock},
+	time::{Duration, SystemTime},
+};
+use tracing::{error, info, warn};
+
+use crate::{
+	jobs::{JobClass, Jobs},
+	logging::{self, LogFormat},
+	os_command,
+	profile::{Profile, Settings},
+	sandbox,
+	storage::Storage,
+};
+
+pub const CONFIG_PATH: &str = "config.ron";
+
+/// Every config field can be overridden with `SELFBOT_<FIELD>` or read from
+/// the file at `SELFBOT_<FIELD>_FILE` (such as a Docker secret).
+const ENV_PREFIX: &str = "SELFBOT_";
+
+/// The config path from `--config <path>`, otherwise `config.ron`.
+pub fn config_path_from_args() -> Result<PathBuf> {
+	let mut args = env::args().skip(1);
+	while let Some(arg) = args.next() {
+		if arg == "--config" {
+			return args
+				.next()
+				.map(PathBuf::from)
+				.ok_or_else(|| anyhow!("--config needs a path after it."));
+		}
+		if let Some(path) = arg.strip_prefix("--config=") {
+			return Ok(PathBuf::from(path));
+		}
+	}
+	Ok(PathBuf::from(CONFIG_PATH))
+}
+
+/// How often the config file is checked for changes.
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
 
 /// No more British.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -11,30 +56,178 @@ impl From<Color> for Colour {
 		Colour::from(color.0)
 	}
 }
+/// Accepts `0xRRGGBB`, `#RRGGBB`, or a plain number.
+impl FromStr for Color {
+	type Err = Error;
+
+	fn from_str(value: &str) -> Result<Self> {
+		let value = value.trim();
+		let parsed = if let Some(hex) =
+			value.strip_prefix("0x").or_else(|| value.strip_prefix('#'))
+		{
+			u32::from_str_radix(hex, 16)
+		} else {
+			value.parse::<u32>()
+		};
+		parsed
+			.map(Color)
+			.map_err(|_| anyhow!("\"{}\" isn't a 0xRRGGBB color.", value))
+	}
+}
 
 #[derive(Debug)]
 pub struct BotData {
-	pub config: Config,
+	config: Arc<RwLock<Arc<Config>>>,
+	/// Every owner's profile, kept in memory so that reading settings
+	/// doesn't need to wait on the database.
+	profiles: RwLock<HashMap<serenity::UserId, Profile>>,
 	pub http: reqwest::Client,
+	pub storage: Storage,
+	pub jobs: Jobs,
 	pub bot_start_time: std::time::Instant,
 }
-impl Default for BotData {
-	fn default() -> Self {
-		Self::new()
-	}
-}
 
 impl BotData {
-	pub fn new() -> Self {
-		let config = Config::new();
+	pub async fn new(config: Config, storage: Storage) -> Result<Self> {
 		let http = reqwest::Client::new();
 		let bot_start_time = std::time::Instant::now();
-		Self {
-			config,
+		let profiles = storage.profiles().await?.into_iter().collect();
+		Ok(Self {
+			config: Arc::new(RwLock::new(Arc::new(config))),
+			profiles: RwLock::new(profiles),
 			http,
+			storage,
+			jobs: Jobs::default(),
 			bot_start_time,
+		})
+	}
+
+	/// A snapshot of the current config.
+	/// Hold on to it for the length of a command so that a reload halfway
+	/// through doesn't mix two configs.
+	pub fn config(&self) -> Arc<Config> {
+		self.config
+			.read()
+			.unwrap_or_else(|e| e.into_inner())
+			.clone()
+	}
+
+	/// The user's profile overrides, which may be empty.
+	pub fn profile(&self, user_id: serenity::UserId) -> Profile {
+		self.profiles
+			.read()
+			.unwrap_or_else(|e| e.into_inner())
+			.get(&user_id)
+			.cloned()
+			.unwrap_or_default()
+	}
+
+	/// The user's profile layered over the current config.
+	pub fn settings(&self, user_id: serenity::UserId) -> Settings {
+		let profiles =
+			self.profiles.read().unwrap_or_else(|e| e.into_inner());
+		Settings::resolve(&self.config(), profiles.get(&user_id))
+	}
+
+	/// Saves the profile to the database, then makes it visible to commands.
+	pub async fn save_profile(
+		&self,
+		user_id: serenity::UserId,
+		profile: Profile,
+	) -> Result<()> {
+		self.storage.save_profile(user_id, &profile).await?;
+		let mut profiles =
+			self.profiles.write().unwrap_or_else(|e| e.into_inner());
+		if profile.is_empty() {
+			profiles.remove(&user_id);
+		} else {
+			profiles.insert(user_id, profile);
 		}
+		Ok(())
 	}
+
+	/// Polls the config file and swaps in new versions as they're saved.
+	/// If a new version doesn't load, the old one is kept and the owners
+	/// are sent the reason.
+	pub fn watch_config(
+		&self,
+		path: impl Into<PathBuf>,
+		discord_http: Arc<serenity::Http>,
+	) {
+		let path = path.into();
+		let config = self.config.clone();
+
+		tokio::spawn(async move {
+			let mut last_modified = modified_time(&path);
+			let mut interval = tokio::time::interval(CONFIG_POLL_INTERVAL);
+
+			loop {
+				interval.tick().await;
+
+				let modified = modified_time(&path);
+				if modified == last_modified {
+					continue;
+				}
+				last_modified = modified;
+
+				let old_config =
+					config.read().unwrap_or_else(|e| e.into_inner()).clone();
+
+				match Config::load(&path) {
+					Ok(new_config) => {
+						if new_config.discord_token
+							!= old_config.discord_token
+							|| new_config.owner_ids != old_config.owner_ids
+						{
+							warn!(
+								"discord_token and owner_ids only take effect \
+								 after a restart."
+							);
+						}
+						logging::set_secrets(&new_config);
+						os_command::set_timeouts(&new_config);
+						sandbox::set_limits(&new_config);
+						*config.write().unwrap_or_else(|e| e.into_inner()) =
+							Arc::new(new_config);
+						info!(path = %path.display(), "Reloaded the config.");
+					}
+					Err(e) => {
+						error!(
+							path = %path.display(),
+							"Kept the old config because the new one failed \
+							 to load: {:#}",
+							e
+						);
+						let message = CreateMessage::new().content(format!(
+							"Your changes to `{}` weren't applied. The \
+							 previous config is still in use.\n```\n{:#}\n```",
+							path.display(),
+							e
+						));
+						for owner_id in &old_config.owner_ids {
+							if let Err(e) = owner_id
+								.direct_message(
+									&discord_http,
+									message.clone(),
+								)
+								.await
+							{
+								error!(
+									owner_id = %owner_id,
+									"Failed to message an owner: {}",
+									e
+								);
+							}
+						}
+					}
+				}
+			}
+		});
+	}
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+	fs::metadata(path).and_then(|m| m.modified()).ok()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,17 +243,387 @@ pub struct Config {
 	pub embed_color: Color,
 	pub randomorg_api_key: Option<String>,
 	pub listenbrainz_user: Option<String>,
+	/// Registers commands to this guild instead of globally while developing.
+	#[serde(default)]
+	pub dev_guild_id: Option<serenity::GuildId>,
+	/// `Pretty` for reading in a terminal or `Json` for log collectors.
+	#[serde(default)]
+	pub log_format: LogFormat,
+	/// Where the SQLite database is kept. Changes need a restart.
+	#[serde(default = "default_database_path")]
+	pub database_path: PathBuf,
+	/// How many seconds an external tool such as ffmpeg may run before it's
+	/// killed.
+	#[serde(default = "default_process_timeout")]
+	pub process_timeout: u64,
+	/// Per-tool overrides for `process_timeout`, keyed by the tool's name
+	/// such as `ffmpeg` or `yt-dlp`.
+	#[serde(default)]
+	pub process_timeouts: HashMap<String, u64>,
+	/// Resource limits for every ffmpeg process, including `/ffmpeg`.
+	#[serde(default)]
+	pub ffmpeg_limits: FfmpegLimits,
+	/// How many jobs of each class may run at once. The rest wait in line.
+	#[serde(default)]
+	pub job_concurrency: JobConcurrency,
+	/// Serves Prometheus metrics on this address, such as `127.0.0.1:9185`.
+	#[serde(default)]
+	pub metrics_address: Option<SocketAddr>,
+	/// Per-command settings keyed by the command's function name, such as
+	/// `youtube` or `convert_context_menu`.
+	#[serde(default)]
+	pub commands: HashMap<String, CommandConfig>,
+}
+impl Config {
+	/// Disabled commands aren't registered with Discord.
+	pub fn command_enabled(&self, name: &str) -> bool {
+		self.commands
+			.get(name)
+			.is_none_or(|command| command.enabled)
+	}
+
+	/// The configured default for a command's `ephemeral` argument.
+	pub fn command_ephemeral(&self, name: &str) -> Option<bool> {
+		self.commands
+			.get(name)
+			.and_then(|command| command.ephemeral)
+	}
+
+	/// Reads and parses the config file, layers the environment overrides
+	/// on top, and validates the result.
+	pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+		let path = path.as_ref();
+		let config_string = fs::read_to_string(path)
+			.with_context(|| format!("Failed to read {}", path.display()))?;
+		let mut config: Config = ron::from_str(&config_string)
+			.with_context(|| format!("Failed to parse {}", path.display()))?;
+		config.apply_env_overrides()?;
+		config
+			.validate()
+			.with_context(|| format!("{} is invalid", path.display()))?;
+		Ok(config)
+	}
+
+	fn apply_env_overrides(&mut self) -> Result<()> {
+		if let Some(value) = env_override("discord_token")? {
+			self.discord_token = value;
+		}
+		if let Some(value) = env_override("owner_ids")? {
+			self.owner_ids = value
+				.split(',')
+				.map(str::trim)
+				.filter(|id| !id.is_empty())
+				.map(|id| parse_user_id("owner_ids", id))
+				.collect::<Result<_>>()?;
+		}
+		if let Some(value) = env_override("application_id")? {
+			self.application_id = parse_user_id("application_id", &value)?;
+		}
+		if let Some(value) = env_override("wolfram_alpha_full_app_id")? {
+			self.wolfram_alpha_full_app_id = non_empty(value);
+		}
+		if let Some(value) = env_override("deepl_target_language")? {
+			self.deepl_target_language = value;
+		}
+		if let Some(value) = env_override("timezone")? {
+			self.timezone = value;
+		}
+		if let Some(value) = env_override("embed_color")? {
+			self.embed_color = value.parse().with_context(|| {
+				format!("{}EMBED_COLOR is invalid", ENV_PREFIX)
+			})?;
+		}
+		if let Some(value) = env_override("randomorg_api_key")? {
+			self.randomorg_api_key = non_empty(value);
+		}
+		if let Some(value) = env_override("listenbrainz_user")? {
+			self.listenbrainz_user = non_empty(value);
+		}
+		if let Some(value) = env_override("dev_guild_id")? {
+			self.dev_guild_id = match non_empty(value) {
+				Some(id) => Some(
+					id.trim()
+						.parse::<NonZeroU64>()
+						.map(serenity::GuildId::from)
+						.map_err(|_| {
+							anyhow!(
+								"{}DEV_GUILD_ID has an invalid guild ID: {}",
+								ENV_PREFIX,
+								id
+							)
+						})?,
+				),
+				None => None,
+			};
+		}
+		if let Some(value) = env_override("log_format")? {
+			self.log_format = ron::from_str(&value).map_err(|_| {
+				anyhow!(
+					"{}LOG_FORMAT must be Pretty or Json: {}",
+					ENV_PREFIX,
+					value
+				)
+			})?;
+		}
+		if let Some(value) = env_override("database_path")? {
+			self.database_path = PathBuf::from(value);
+		}
+		if let Some(value) = env_override("process_timeout")? {
+			self.process_timeout = value.trim().parse().map_err(|_| {
+				anyhow!(
+					"{}PROCESS_TIMEOUT must be a number of seconds: {}",
+					ENV_PREFIX,
+					value
+				)
+			})?;
+		}
+		if let Some(value) = env_override("process_timeouts")? {
+			self.process_timeouts =
+				ron::from_str(&value).with_context(|| {
+					format!("Failed to parse {}PROCESS_TIMEOUTS", ENV_PREFIX)
+				})?;
+		}
+		if let Some(value) = env_override("ffmpeg_limits")? {
+			self.ffmpeg_limits =
+				ron::from_str(&value).with_context(|| {
+					format!("Failed to parse {}FFMPEG_LIMITS", ENV_PREFIX)
+				})?;
+		}
+		if let Some(value) = env_override("job_concurrency")? {
+			self.job_concurrency =
+				ron::from_str(&value).with_context(|| {
+					format!("Failed to parse {}JOB_CONCURRENCY", ENV_PREFIX)
+				})?;
+		}
+		if let Some(value) = env_override("metrics_address")? {
+			self.metrics_address = match non_empty(value) {
+				Some(address) => {
+					Some(address.trim().parse().map_err(|_| {
+						anyhow!(
+							"{}METRICS_ADDRESS isn't an address such as \
+						 127.0.0.1:9185: {}",
+							ENV_PREFIX,
+							address
+						)
+					})?)
+				}
+				None => None,
+			};
+		}
+		if let Some(value) = env_override("commands")? {
+			self.commands = ron::from_str(&value).with_context(|| {
+				format!("Failed to parse {}COMMANDS", ENV_PREFIX)
+			})?;
+		}
+		Ok(())
+	}
+
+	/// Checks the values that parse fine but would break commands later.
+	/// Every problem is reported at once instead of one per restart.
+	pub fn validate(&self) -> Result<()> {
+		let mut problems = Vec::new();
+
+		if self.discord_token.trim().is_empty() {
+			problems.push("`discord_token` is missing.".to_string());
+		}
+		if self.owner_ids.is_empty() {
+			problems.push(
+				"`owner_ids` is empty so nobody could use the bot."
+					.to_string(),
+			);
+		}
+		if self.deepl_target_language.trim().is_empty() {
+			problems.push(
+				"`deepl_target_language` is empty. Use the language name as \
+				 it appears on the DeepL website."
+					.to_string(),
+			);
+		}
+		if self.timezone.parse::<chrono_tz::Tz>().is_err() {
+			problems.push(format!(
+				"`timezone` \"{}\" isn't a tz database time zone such as \
+				 \"America/New_York\".",
+				self.timezone
+			));
+		}
+		if self.process_timeout == 0 {
+			problems
+				.push("`process_timeout` must be more than 0.".to_string());
+		}
+		for (tool, timeout) in &self.process_timeouts {
+			if *timeout == 0 {
+				problems.push(format!(
+					"`process_timeouts` for {} must be more than 0.",
+					tool
+				));
+			}
+		}
+		let limits = &self.ffmpeg_limits;
+		if limits.cpu_seconds == 0
+			|| limits.memory_mb == 0
+			|| limits.output_file_mb == 0
+		{
+			problems
+				.push("`ffmpeg_limits` must all be more than 0.".to_string());
+		}
+		let concurrency = &self.job_concurrency;
+		if concurrency.video == 0
+			|| concurrency.image == 0
+			|| concurrency.download == 0
+		{
+			problems.push(
+				"`job_concurrency` must all be more than 0.".to_string(),
+			);
+		}
+		if self.embed_color.0 > 0xFFFFFF {
+			problems.push(format!(
+				"`embed_color` 0x{:X} isn't a 0xRRGGBB color.",
+				self.embed_color.0
+			));
+		}
+
+		if problems.is_empty() {
+			Ok(())
+		} else {
+			Err(anyhow!("\n- {}", problems.join("\n- ")))
+		}
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandConfig {
+	#[serde(default = "default_enabled")]
+	pub enabled: bool,
+	/// Used when the `ephemeral` argument isn't given.
+	#[serde(default)]
+	pub ephemeral: Option<bool>,
+}
+fn default_enabled() -> bool {
+	true
+}
+
+/// Enforced with rlimits, so a process that goes over is stopped by the OS.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FfmpegLimits {
+	/// Seconds of CPU time, counted across every thread.
+	#[serde(default = "default_ffmpeg_cpu_seconds")]
+	pub cpu_seconds: u64,
+	/// Address space in megabytes.
+	#[serde(default = "default_ffmpeg_memory_mb")]
+	pub memory_mb: u64,
+	/// The largest file ffmpeg may write, in megabytes.
+	#[serde(default = "default_ffmpeg_output_file_mb")]
+	pub output_file_mb: u64,
 }
-impl Default for Config {
+impl Default for FfmpegLimits {
 	fn default() -> Self {
-		Self::new()
+		Self {
+			cpu_seconds: default_ffmpeg_cpu_seconds(),
+			memory_mb: default_ffmpeg_memory_mb(),
+			output_file_mb: default_ffmpeg_output_file_mb(),
+		}
 	}
 }
-impl Config {
-	pub fn new() -> Self {
-		let config_string = fs::read_to_string("config.ron").unwrap();
-		ron::from_str(&config_string).unwrap()
+fn default_ffmpeg_cpu_seconds() -> u64 {
+	20 * 60
+}
+fn default_ffmpeg_memory_mb() -> u64 {
+	4096
+}
+fn default_ffmpeg_output_file_mb() -> u64 {
+	1024
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobConcurrency {
+	/// ffmpeg and compression.
+	#[serde(default = "default_video_concurrency")]
+	pub video: usize,
+	/// Image encoders such as cjxl and img2webp.
+	#[serde(default = "default_image_concurrency")]
+	pub image: usize,
+	/// Downloaders such as yt-dlp.
+	#[serde(default = "default_download_concurrency")]
+	pub download: usize,
+}
+impl JobConcurrency {
+	pub fn limit(&self, class: JobClass) -> usize {
+		match class {
+			JobClass::Video => self.video,
+			JobClass::Image => self.image,
+			JobClass::Download => self.download,
+		}
+	}
+}
+impl Default for JobConcurrency {
+	fn default() -> Self {
+		Self {
+			video: default_video_concurrency(),
+			image: default_image_concurrency(),
+			download: default_download_concurrency(),
+		}
+	}
+}
+fn default_video_concurrency() -> usize {
+	1
+}
+fn default_image_concurrency() -> usize {
+	2
+}
+fn default_download_concurrency() -> usize {
+	2
+}
+
+fn default_process_timeout() -> u64 {
+	10 * 60
+}
+
+fn default_database_path() -> PathBuf {
+	PathBuf::from("data/selfbot.sqlite3")
+}
+
+/// Reads `SELFBOT_<FIELD>`, falling back to the contents of the file at
+/// `SELFBOT_<FIELD>_FILE`.
+fn env_override(field: &str) -> Result<Option<String>> {
+	let key = format!("{}{}", ENV_PREFIX, field.to_uppercase());
+	if let Ok(value) = env::var(&key) {
+		return Ok(Some(value));
+	}
+
+	let file_key = format!("{}_FILE", key);
+	if let Ok(path) = env::var(&file_key) {
+		let value = fs::read_to_string(&path).with_context(|| {
+			format!("Failed to read {} ({})", file_key, path)
+		})?;
+		// Secret files almost always end with a newline.
+		return Ok(Some(value.trim_end_matches(['\r', '\n']).to_string()));
 	}
+
+	Ok(None)
+}
+
+/// An empty override clears an optional field.
+fn non_empty(value: String) -> Option<String> {
+	if value.trim().is_empty() {
+		None
+	} else {
+		Some(value)
+	}
+}
+
+fn parse_user_id(field: &str, value: &str) -> Result<serenity::UserId> {
+	value
+		.trim()
+		.parse::<NonZeroU64>()
+		.map(serenity::UserId::from)
+		.map_err(|_| {
+			anyhow!(
+				"{}{} has an invalid user ID: {}",
+				ENV_PREFIX,
+				field.to_uppercase(),
+				value
+			)
+		})
 }
 
 pub type Context<'a> = poise::Context<'a, BotData, Error>;
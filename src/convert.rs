@@ -0,0 +1,525 @@
+use std::{
+	path::{Path, PathBuf},
+	process::{self, Output},
+};
+
+use anyhow::{anyhow, Result};
+use poise::ChoiceParameter;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::{
+	media::{ffmpeg_command, ffmpeg_input, run_ffmpeg_with_progress},
+	os_command::run_os_command,
+	probe::{MediaInfo, MediaType},
+	sniff::FileFormat,
+	workspace::Workspace,
+};
+
+/// The quality lossy encodes use when none is given.
+pub const DEFAULT_QUALITY: u8 = 85;
+
+/// The image formats files can be converted to.
+#[derive(
+	Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, ChoiceParameter,
+)]
+pub enum TargetFormat {
+	#[name = "WebP"]
+	WebP,
+	#[name = "JXL"]
+	#[name = "JPEG XL"]
+	Jxl,
+	#[name = "AVIF"]
+	Avif,
+	#[name = "PNG"]
+	Png,
+	#[name = "JPEG"]
+	#[name = "JPG"]
+	Jpeg,
+	#[name = "GIF"]
+	Gif,
+	#[name = "APNG"]
+	Apng,
+}
+impl TargetFormat {
+	pub fn extension(&self) -> &'static str {
+		match self {
+			Self::WebP => "webp",
+			Self::Jxl => "jxl",
+			Self::Avif => "avif",
+			// Discord only animates APNGs named `.png`.
+			Self::Png | Self::Apng => "png",
+			Self::Jpeg => "jpg",
+			Self::Gif => "gif",
+		}
+	}
+
+	/// The effort used when none is given. Everything defaults to its
+	/// slowest setting except AVIF, since avifenc's slowest speeds take
+	/// minutes on large images.
+	pub fn default_effort(&self) -> u8 {
+		match self {
+			Self::Avif => 6,
+			_ => 10,
+		}
+	}
+}
+
+/// How a file is converted. Lossless and lossy only apply to WebP, JXL and
+/// AVIF, since PNG, APNG and GIF are always lossless and JPEG never is.
+#[derive(Debug, Clone, Copy)]
+pub struct ConvertOptions {
+	pub format: TargetFormat,
+	/// `None` for lossless, otherwise the quality from 0 to 100.
+	pub quality: Option<u8>,
+	/// From 1 to 10, where 10 is the slowest and smallest.
+	pub effort: u8,
+	pub max_width: Option<u32>,
+	pub max_height: Option<u32>,
+}
+
+impl ConvertOptions {
+	fn resizes(&self) -> bool {
+		self.max_width.is_some() || self.max_height.is_some()
+	}
+
+	/// Fits the picture inside the maximum dimensions without ever scaling
+	/// it up or changing its aspect ratio.
+	fn scale_filter(&self) -> Option<String> {
+		self.resizes().then(|| {
+			format!(
+				"scale=w='min(iw,{})':h='min(ih,{})':\
+				 force_original_aspect_ratio=decrease",
+				self.max_width
+					.map_or("iw".to_string(), |width| width.to_string()),
+				self.max_height
+					.map_or("ih".to_string(), |height| height.to_string()),
+			)
+		})
+	}
+}
+
+/// Converts `input` to the target format in the workspace's outputs and
+/// returns the path. `format` is what the input really is and `info` is
+/// what ffprobe found in it, both from
+/// [`crate::workspace::Workspace::download`].
+///
+/// Each format goes to the tool that does it best: img2webp and gif2webp
+/// for WebP, cjxl for JXL, avifenc for AVIF, and ffmpeg for the rest and
+/// for anything the others can't read.
+pub async fn convert_file(
+	input: &Path,
+	format: Option<FileFormat>,
+	info: &MediaInfo,
+	workspace: &Workspace,
+	options: &ConvertOptions,
+) -> Result<PathBuf> {
+	let animated = is_animated(format, info);
+	let output = workspace
+		.output_path(&format!("converted.{}", options.format.extension()));
+	debug!(?format, animated, ?options, "Converting a file.");
+
+	let ffmpeg = |ffmpeg_format| {
+		encode_with_ffmpeg(input, info, options, ffmpeg_format, &output)
+	};
+	let result = match options.format {
+		TargetFormat::WebP => {
+			encode_webp(
+				input, format, info, workspace, options, animated, &output,
+			)
+			.await?
+		}
+		TargetFormat::Jxl => {
+			encode_jxl(
+				input, format, info, workspace, options, animated, &output,
+			)
+			.await?
+		}
+		TargetFormat::Avif => {
+			encode_avif(
+				input, format, info, workspace, options, animated, &output,
+			)
+			.await?
+		}
+		TargetFormat::Png => ffmpeg(FfmpegFormat::Png).await?,
+		TargetFormat::Apng => ffmpeg(FfmpegFormat::Apng).await?,
+		TargetFormat::Jpeg => ffmpeg(FfmpegFormat::Jpeg).await?,
+		TargetFormat::Gif => ffmpeg(FfmpegFormat::Gif).await?,
+	};
+
+	check(result)?;
+	Ok(output)
+}
+
+/// GIFs and APNGs count as animated even with one frame, since ffprobe
+/// rarely knows their frame count and every tool handles a single frame.
+fn is_animated(format: Option<FileFormat>, info: &MediaInfo) -> bool {
+	matches!(format, Some(FileFormat::Gif | FileFormat::Apng))
+		|| info.media_type() == MediaType::Video
+		|| info
+			.video_stream()
+			.and_then(|video| video.frames)
+			.is_some_and(|frames| frames > 1)
+}
+
+async fn encode_webp(
+	input: &Path,
+	format: Option<FileFormat>,
+	info: &MediaInfo,
+	workspace: &Workspace,
+	options: &ConvertOptions,
+	animated: bool,
+	output: &Path,
+) -> Result<Output> {
+	// Maps effort 1–10 onto `-m 0` to `-m 6`.
+	let method = ((options.effort.clamp(1, 10) - 1) * 6 + 4) / 9;
+	let quality = options.quality.unwrap_or(DEFAULT_QUALITY).to_string();
+
+	if animated && format == Some(FileFormat::Gif) && !options.resizes() {
+		let mut gif2webp_command = process::Command::new("gif2webp");
+		gif2webp_command.args(["-v", "-mt", "-m", &method.to_string()]);
+		if options.quality.is_some() {
+			gif2webp_command.args(["-lossy", "-q", &quality]);
+		}
+		gif2webp_command.arg(input).arg("-o").arg(output);
+		return run_os_command("gif2webp", gif2webp_command).await;
+	}
+
+	if animated {
+		// img2webp only takes stills, so animations go through libwebp in
+		// ffmpeg instead.
+		let mut ffmpeg_command = ffmpeg_command();
+		ffmpeg_command.arg("-y").args(ffmpeg_input(input));
+		if let Some(scale) = options.scale_filter() {
+			ffmpeg_command.args(["-vf", &scale]);
+		}
+		ffmpeg_command.args([
+			"-an",
+			"-c:v",
+			"libwebp_anim",
+			"-compression_level",
+			&method.to_string(),
+			"-loop",
+			"0",
+		]);
+		match options.quality {
+			Some(_) => {
+				ffmpeg_command.args(["-lossless", "0", "-quality", &quality])
+			}
+			None => {
+				ffmpeg_command.args(["-lossless", "1", "-quality", "100"])
+			}
+		};
+		ffmpeg_command.args(["-f", "webp"]).arg(output);
+		return run_ffmpeg_with_progress(ffmpeg_command, info.duration).await;
+	}
+
+	let input = readable_still(
+		input,
+		format,
+		workspace,
+		options,
+		&[
+			FileFormat::Png,
+			FileFormat::Jpeg,
+			FileFormat::Tiff,
+			FileFormat::WebP,
+		],
+	)
+	.await?;
+	// The frame options have to come before the frame they're for.
+	let mut img2webp_command = process::Command::new("img2webp");
+	img2webp_command.args(["-v", "-sharp_yuv"]);
+	match options.quality {
+		Some(_) => img2webp_command.args(["-lossy", "-q", &quality]),
+		None => img2webp_command.arg("-lossless"),
+	};
+	img2webp_command
+		.args(["-m", &method.to_string()])
+		.arg(&input)
+		.arg("-o")
+		.arg(output);
+	run_os_command("img2webp", img2webp_command).await
+}
+
+async fn encode_jxl(
+	input: &Path,
+	format: Option<FileFormat>,
+	info: &MediaInfo,
+	workspace: &Workspace,
+	options: &ConvertOptions,
+	animated: bool,
+	output: &Path,
+) -> Result<Output> {
+	// JPEGs go to cjxl as they are unless they're resized.
+	let is_jpeg = format == Some(FileFormat::Jpeg) && !options.resizes();
+	let input = if animated {
+		readable_animation(
+			input,
+			format,
+			info,
+			workspace,
+			options,
+			&[FileFormat::Gif, FileFormat::Apng],
+		)
+		.await?
+	} else {
+		readable_still(
+			input,
+			format,
+			workspace,
+			options,
+			&[FileFormat::Png, FileFormat::Jpeg, FileFormat::Gif],
+		)
+		.await?
+	};
+
+	let mut cjxl_command = process::Command::new("cjxl");
+	cjxl_command.arg("-v").arg(&input).arg(output);
+	match options.quality {
+		Some(quality) => {
+			cjxl_command.args(["-q", &quality.to_string()]);
+			// Otherwise cjxl recompresses JPEGs losslessly whatever the
+			// quality is.
+			if is_jpeg {
+				cjxl_command.arg("--lossless_jpeg=0");
+			}
+		}
+		None => {
+			cjxl_command.args(["-d", "0"]);
+		}
+	}
+	cjxl_command.args(["-e", &options.effort.clamp(1, 10).to_string()]);
+	run_os_command("cjxl", cjxl_command).await
+}
+
+async fn encode_avif(
+	input: &Path,
+	format: Option<FileFormat>,
+	info: &MediaInfo,
+	workspace: &Workspace,
+	options: &ConvertOptions,
+	animated: bool,
+	output: &Path,
+) -> Result<Output> {
+	// avifenc only reads animations from Y4M, which has no alpha channel.
+	let input = if animated {
+		let y4m = workspace.path().join("intermediate.y4m");
+		let mut ffmpeg_command = ffmpeg_command();
+		ffmpeg_command.arg("-y").args(ffmpeg_input(input));
+		if let Some(scale) = options.scale_filter() {
+			ffmpeg_command.args(["-vf", &scale]);
+		}
+		ffmpeg_command
+			.args(["-an", "-pix_fmt", "yuv444p", "-f", "yuv4mpegpipe"])
+			.arg(&y4m);
+		check(
+			run_ffmpeg_with_progress(ffmpeg_command, info.duration).await?,
+		)?;
+		y4m
+	} else {
+		readable_still(
+			input,
+			format,
+			workspace,
+			options,
+			&[FileFormat::Png, FileFormat::Jpeg],
+		)
+		.await?
+	};
+
+	let mut avifenc_command = process::Command::new("avifenc");
+	avifenc_command.args([
+		"--jobs",
+		"all",
+		"--speed",
+		&(10 - options.effort.clamp(1, 10)).to_string(),
+	]);
+	match options.quality {
+		Some(quality) => {
+			avifenc_command.args(["-q", &quality.to_string()]);
+		}
+		None => {
+			avifenc_command.arg("--lossless");
+		}
+	}
+	avifenc_command.arg(&input).arg(output);
+	run_os_command("avifenc", avifenc_command).await
+}
+
+/// The formats ffmpeg handles well enough on its own.
+#[derive(Debug, Clone, Copy)]
+enum FfmpegFormat {
+	Png,
+	Apng,
+	Jpeg,
+	Gif,
+}
+
+async fn encode_with_ffmpeg(
+	input: &Path,
+	info: &MediaInfo,
+	options: &ConvertOptions,
+	format: FfmpegFormat,
+	output: &Path,
+) -> Result<Output> {
+	let scale = options.scale_filter();
+	let compression_level = (options.effort.clamp(1, 10) - 1).to_string();
+
+	let mut ffmpeg_command = ffmpeg_command();
+	ffmpeg_command
+		.arg("-y")
+		.args(ffmpeg_input(input))
+		.arg("-an");
+	match format {
+		FfmpegFormat::Png => {
+			if let Some(scale) = &scale {
+				ffmpeg_command.args(["-vf", scale]);
+			}
+			ffmpeg_command.args([
+				"-frames:v",
+				"1",
+				"-c:v",
+				"png",
+				"-pred",
+				"mixed",
+				"-compression_level",
+				&compression_level,
+				"-f",
+				"image2",
+			]);
+		}
+		FfmpegFormat::Apng => {
+			if let Some(scale) = &scale {
+				ffmpeg_command.args(["-vf", scale]);
+			}
+			ffmpeg_command.args([
+				"-c:v",
+				"apng",
+				"-pred",
+				"mixed",
+				"-compression_level",
+				&compression_level,
+				"-plays",
+				"0",
+				"-f",
+				"apng",
+			]);
+		}
+		FfmpegFormat::Jpeg => {
+			// JPEGs have no alpha, so transparency becomes white instead of
+			// whatever was under it.
+			let mut filter = "split[background][picture];\
+			                  [background]drawbox=c=white:t=fill[background];\
+			                  [background][picture]overlay=format=auto"
+				.to_string();
+			if let Some(scale) = &scale {
+				filter = format!("{},{}", scale, filter);
+			}
+			// ffmpeg's JPEG quality goes from 2 (best) to 31 (worst).
+			let quality = options.quality.unwrap_or(DEFAULT_QUALITY) as u32;
+			let qscale = 31 - quality.min(100) * 29 / 100;
+			ffmpeg_command.args([
+				"-filter_complex",
+				&filter,
+				"-frames:v",
+				"1",
+				"-c:v",
+				"mjpeg",
+				"-pix_fmt",
+				"yuvj444p",
+				"-q:v",
+				&qscale.to_string(),
+				"-f",
+				"image2",
+			]);
+		}
+		FfmpegFormat::Gif => {
+			// One palette for the whole animation, built from what changes
+			// between frames.
+			let mut filter = "split[frames][palette_source];\
+			                  [palette_source]palettegen=stats_mode=diff\
+			                  [palette];\
+			                  [frames][palette]paletteuse=dither=sierra2_4a"
+				.to_string();
+			if let Some(scale) = &scale {
+				filter = format!("{},{}", scale, filter);
+			}
+			ffmpeg_command.args([
+				"-filter_complex",
+				&filter,
+				"-loop",
+				"0",
+				"-f",
+				"gif",
+			]);
+		}
+	}
+	ffmpeg_command.arg(output);
+	run_ffmpeg_with_progress(ffmpeg_command, info.duration).await
+}
+
+/// The input as it is when the tool can read it and nothing is resized,
+/// otherwise the first frame as a PNG.
+async fn readable_still(
+	input: &Path,
+	format: Option<FileFormat>,
+	workspace: &Workspace,
+	options: &ConvertOptions,
+	readable: &[FileFormat],
+) -> Result<PathBuf> {
+	if format.is_some_and(|format| readable.contains(&format))
+		&& !options.resizes()
+	{
+		return Ok(input.to_path_buf());
+	}
+	let png = workspace.path().join("intermediate.png");
+	let mut ffmpeg_command = ffmpeg_command();
+	ffmpeg_command.arg("-y").args(ffmpeg_input(input));
+	if let Some(scale) = options.scale_filter() {
+		ffmpeg_command.args(["-vf", &scale]);
+	}
+	ffmpeg_command
+		.args(["-frames:v", "1", "-c:v", "png", "-f", "image2"])
+		.arg(&png);
+	check(run_ffmpeg_with_progress(ffmpeg_command, None).await?)?;
+	Ok(png)
+}
+
+/// Like [`readable_still`] for animations, which become an APNG.
+async fn readable_animation(
+	input: &Path,
+	format: Option<FileFormat>,
+	info: &MediaInfo,
+	workspace: &Workspace,
+	options: &ConvertOptions,
+	readable: &[FileFormat],
+) -> Result<PathBuf> {
+	if format.is_some_and(|format| readable.contains(&format))
+		&& !options.resizes()
+	{
+		return Ok(input.to_path_buf());
+	}
+	let apng = workspace.path().join("intermediate.apng");
+	let mut ffmpeg_command = ffmpeg_command();
+	ffmpeg_command.arg("-y").args(ffmpeg_input(input));
+	if let Some(scale) = options.scale_filter() {
+		ffmpeg_command.args(["-vf", &scale]);
+	}
+	ffmpeg_command
+		.args(["-an", "-c:v", "apng", "-plays", "0", "-f", "apng"])
+		.arg(&apng);
+	check(run_ffmpeg_with_progress(ffmpeg_command, info.duration).await?)?;
+	Ok(apng)
+}
+
+fn check(output: Output) -> Result<()> {
+	if output.status.success() {
+		Ok(())
+	} else {
+		Err(anyhow!(
+			"```\n{}\n```",
+			String::from_utf8_lossy(&output.stderr)
+		))
+	}
+}
@@ -0,0 +1,142 @@
+use std::{backtrace::Backtrace, cell::RefCell, panic};
+
+use anyhow::Error;
+use poise::{
+	serenity_prelude::{
+		CreateAllowedMentions, CreateEmbed, CreateEmbedFooter,
+	},
+	CreateReply, FrameworkError,
+};
+
+use tracing::error;
+
+use crate::{
+	config::{BotData, Context},
+	history::{self, Outcome},
+	jobs::Cancelled,
+	logging::redact,
+	metrics,
+};
+
+const ERROR_COLOR: u32 = 0xed4245;
+/// Embed descriptions can't be longer than 4096 characters.
+const MAX_DESCRIPTION_LENGTH: usize = 4000;
+
+thread_local! {
+	/// The ID the panic hook logged the last panic on this thread under.
+	/// Command panics are caught on the thread they happened on, so the
+	/// error handler can pick it up and show the same ID.
+	static LAST_PANIC_ID: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// A short ID that ties the message the user sees to the log.
+fn new_error_id() -> String {
+	format!("{:08x}", rand::random::<u32>())
+}
+
+/// Logs every panic with its backtrace under a new error ID, then runs the
+/// default hook.
+pub fn install_panic_hook() {
+	let default_hook = panic::take_hook();
+	panic::set_hook(Box::new(move |info| {
+		let error_id = new_error_id();
+		error!(
+			error_id,
+			"{}\n{}",
+			redact(&info.to_string()),
+			Backtrace::force_capture()
+		);
+		LAST_PANIC_ID.with(|id| *id.borrow_mut() = Some(error_id));
+		default_hook(info);
+	}));
+}
+
+pub async fn on_error(error: FrameworkError<'_, BotData, Error>) {
+	match error {
+		FrameworkError::Command { error, ctx, .. }
+			if error.is::<Cancelled>() =>
+		{
+			metrics::observe_command_failure(
+				&metrics::command_label(ctx.command()),
+				"cancelled",
+			);
+			history::record(ctx, Outcome::Error, Some(error.to_string()))
+				.await;
+		}
+		FrameworkError::Command { error, ctx, .. } => {
+			let error_id = new_error_id();
+			// {:?} prints the whole chain and the backtrace if one was
+			// captured.
+			error!(
+				error_id,
+				command = command_name(&ctx),
+				"Command failed: {}",
+				redact(&format!("{:?}", error))
+			);
+			metrics::observe_command_failure(
+				&metrics::command_label(ctx.command()),
+				"error",
+			);
+			history::record(ctx, Outcome::Error, Some(error.to_string()))
+				.await;
+			report(ctx, &error_id, &redact(&error.to_string())).await;
+		}
+		FrameworkError::CommandPanic { payload, ctx, .. } => {
+			let error_id = LAST_PANIC_ID
+				.with(|id| id.borrow_mut().take())
+				.unwrap_or_else(new_error_id);
+			error!(
+				error_id,
+				command = command_name(&ctx),
+				"Command panicked: {}",
+				redact(payload.as_deref().unwrap_or("unknown payload"))
+			);
+			metrics::observe_command_failure(
+				&metrics::command_label(ctx.command()),
+				"panic",
+			);
+			history::record(ctx, Outcome::Panic, payload.clone()).await;
+			report(ctx, &error_id, "The command crashed unexpectedly.").await;
+		}
+		error => {
+			if let Err(e) = poise::builtins::on_error(error).await {
+				error!("Failed to handle an error: {}", e);
+			}
+		}
+	}
+}
+
+fn command_name(ctx: &Context<'_>) -> String {
+	let command = ctx.command();
+	match &command.context_menu_name {
+		Some(name) => name.clone(),
+		None => format!("/{}", command.qualified_name),
+	}
+}
+
+/// Sends the error to the user as an ephemeral embed.
+async fn report(ctx: Context<'_>, error_id: &str, message: &str) {
+	let mut description = message.to_string();
+	if description.len() > MAX_DESCRIPTION_LENGTH {
+		let mut end = MAX_DESCRIPTION_LENGTH;
+		while !description.is_char_boundary(end) {
+			end -= 1;
+		}
+		description.truncate(end);
+		description.push('…');
+	}
+
+	let embed = CreateEmbed::new()
+		.title(format!("{} failed", command_name(&ctx)))
+		.description(description)
+		.color(ERROR_COLOR)
+		.footer(CreateEmbedFooter::new(format!("Error ID: {}", error_id)));
+	let reply = CreateReply::default()
+		.allowed_mentions(CreateAllowedMentions::default())
+		.embed(embed)
+		.ephemeral(true);
+
+	if let Err(e) = ctx.send(reply).await {
+		error!(error_id, "Failed to send the error: {}", e);
+	}
+}
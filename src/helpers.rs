@@ -1,17 +1,31 @@
 use std::{
 	ffi::OsStr,
-	fs::{self},
+	future::Future,
 	path::{Path, PathBuf},
-	time::Duration,
+	sync::LazyLock,
+	time::{Duration, Instant},
 };
 
 use anyhow::{anyhow, Result};
+use byte_unit::{Byte, UnitType};
 use poise::{
-	serenity_prelude::{Attachment, CreateAttachment, EmbedThumbnail},
+	serenity_prelude::{
+		utils::parse_emoji, Attachment, CreateAttachment, EmbedImage,
+		EmbedThumbnail, EmbedVideo, EmojiIdentifier, Http, ImageHash,
+		Message, StickerFormatType, StickerItem, User,
+	},
 	CreateReply,
 };
+use regex::Regex;
 use reqwest::header;
 
+use crate::{
+	config::{ApplicationContext, Context},
+	logging, metrics,
+	profile::Settings,
+	sniff,
+};
+
 #[macro_export]
 macro_rules! crunch {
 	($($name:ident),* $(,)?) => {
@@ -48,11 +62,76 @@ impl CreateReplyExt for CreateReply {
 	}
 }
 
-pub fn easy_set_file_name(path: &str, name: &str) -> Box<str> {
+pub trait ContextExt {
+	/// The `ephemeral` value to use when the user didn't give one.
+	/// The config's `commands` section takes priority, then the user's
+	/// profile, then the command's own default.
+	fn default_ephemeral(&self, command_default: bool) -> bool;
+
+	/// The settings for whoever ran the command.
+	fn settings(&self) -> Settings;
+}
+impl ContextExt for Context<'_> {
+	fn default_ephemeral(&self, command_default: bool) -> bool {
+		self.data()
+			.config()
+			.command_ephemeral(&self.command().identifying_name)
+			.or(self.settings().ephemeral)
+			.unwrap_or(command_default)
+	}
+
+	fn settings(&self) -> Settings {
+		self.data().settings(self.author().id)
+	}
+}
+impl ContextExt for ApplicationContext<'_> {
+	fn default_ephemeral(&self, command_default: bool) -> bool {
+		poise::Context::Application(*self).default_ephemeral(command_default)
+	}
+
+	fn settings(&self) -> Settings {
+		poise::Context::Application(*self).settings()
+	}
+}
+
+pub trait RequestBuilderExt {
+	/// Sends the request and records its latency and status per host.
+	fn send_measured(
+		self,
+	) -> impl Future<Output = reqwest::Result<reqwest::Response>> + Send;
+}
+impl RequestBuilderExt for reqwest::RequestBuilder {
+	async fn send_measured(self) -> reqwest::Result<reqwest::Response> {
+		let (client, request) = self.build_split();
+		let request = request?;
+		let host = request.url().host_str().unwrap_or("unknown").to_string();
+		let start = Instant::now();
+		let response = client.execute(request).await;
+		metrics::observe_http(
+			&host,
+			start,
+			response.as_ref().ok().map(|r| r.status().as_u16()),
+		);
+		response
+	}
+}
+
+/// Renames a file to `name`, keeping its extension, or the one its data
+/// has when the two disagree.
+pub fn easy_set_file_name(path: &str, name: &str, data: &[u8]) -> Box<str> {
 	let pathified = Path::new(path);
+	let extension = match (sniff::sniff(data), pathified.extension()) {
+		(Some(format), Some(extension))
+			if format.matches_extension(&extension.to_string_lossy()) =>
+		{
+			extension
+		}
+		(Some(format), _) => OsStr::new(format.extension()),
+		(None, extension) => extension.unwrap_or(OsStr::new("png")),
+	};
 	pathified
 		.with_file_name(name)
-		.with_extension(pathified.extension().unwrap_or(OsStr::new("png")))
+		.with_extension(extension)
 		.to_str()
 		.unwrap_or(path)
 		.into()
@@ -77,62 +156,280 @@ pub async fn wait_for_element(
 		.await?)
 }
 
-pub fn safe_delete(path: &PathBuf) -> Result<bool> {
-	if fs::exists(path)? {
-		fs::remove_file(path)?;
-		Ok(true)
-	} else {
-		Ok(false)
-	}
-}
+/// Custom emoji in message content, such as `<:name:123>` or
+/// `<a:name:123>`.
+static CUSTOM_EMOJI_REGEX: LazyLock<Regex> =
+	LazyLock::new(|| Regex::new(r"<a?:\w+:\d+>").unwrap());
 
+/// Media a command can use from a message.
 #[derive(Debug, Clone)]
 pub enum AttachmentOrThumbnail {
 	Attachment(Attachment),
 	Embed(EmbedThumbnail),
+	EmbedImage(EmbedImage),
+	EmbedVideo(EmbedVideo),
+	Sticker(StickerItem),
+	Emoji(EmojiIdentifier),
+	Avatar(Box<User>),
+	Banner(Box<User>),
 }
 impl AttachmentOrThumbnail {
+	/// Everything in the message that can be downloaded, in the order
+	/// Discord shows it. Embed media without a proxy URL, such as YouTube
+	/// players, and Lottie stickers are left out.
+	pub fn from_message(message: &Message) -> Vec<Self> {
+		let mut media = message
+			.attachments
+			.iter()
+			.cloned()
+			.map(Self::Attachment)
+			.collect::<Vec<_>>();
+		for embed in &message.embeds {
+			if let Some(image) = &embed.image {
+				if image.proxy_url.is_some() {
+					media.push(Self::EmbedImage(image.clone()));
+				}
+			}
+			if let Some(thumbnail) = &embed.thumbnail {
+				if thumbnail.proxy_url.is_some() {
+					media.push(Self::Embed(thumbnail.clone()));
+				}
+			}
+			if let Some(video) = &embed.video {
+				if video.proxy_url.is_some() {
+					media.push(Self::EmbedVideo(video.clone()));
+				}
+			}
+		}
+		media.extend(
+			message
+				.sticker_items
+				.iter()
+				.filter(|sticker| {
+					matches!(
+						sticker.format_type,
+						StickerFormatType::Png
+							| StickerFormatType::Apng
+							| StickerFormatType::Gif
+					)
+				})
+				.cloned()
+				.map(Self::Sticker),
+		);
+		let mut emoji_ids = Vec::new();
+		for mention in CUSTOM_EMOJI_REGEX.find_iter(&message.content) {
+			if let Some(emoji) = parse_emoji(mention.as_str()) {
+				if !emoji_ids.contains(&emoji.id) {
+					emoji_ids.push(emoji.id);
+					media.push(Self::Emoji(emoji));
+				}
+			}
+		}
+		media
+	}
+
+	/// The user's avatar, or their default one, and their banner if they
+	/// have one. Banners only come with a fetched user, so this fetches it.
+	pub async fn from_user(http: &Http, user: &User) -> Result<Vec<Self>> {
+		let user = http.get_user(user.id).await?;
+		let mut media = vec![Self::Avatar(Box::new(user.clone()))];
+		if user.banner.is_some() {
+			media.push(Self::Banner(Box::new(user)));
+		}
+		Ok(media)
+	}
+
+	/// Where the file is downloaded from.
+	pub fn url(&self) -> Option<String> {
+		match self {
+			Self::Attachment(a) => Some(a.url.clone()),
+			Self::Embed(e) => e.proxy_url.clone(),
+			Self::EmbedImage(e) => e.proxy_url.clone(),
+			Self::EmbedVideo(e) => e.proxy_url.clone(),
+			// The CDN only serves GIF stickers as their first frame.
+			Self::Sticker(sticker) => match sticker.format_type {
+				StickerFormatType::Png | StickerFormatType::Apng => Some(
+					format!("{}/stickers/{}.png", DISCORD_CDN, sticker.id),
+				),
+				StickerFormatType::Gif => Some(format!(
+					"{}/stickers/{}.gif",
+					DISCORD_MEDIA_PROXY, sticker.id
+				)),
+				_ => None,
+			},
+			Self::Emoji(emoji) => Some(emoji.url()),
+			Self::Avatar(user) => Some(match &user.avatar {
+				Some(hash) => cdn_image_url("avatars", user, hash),
+				None => user.default_avatar_url(),
+			}),
+			Self::Banner(user) => user
+				.banner
+				.as_ref()
+				.map(|hash| cdn_image_url("banners", user, hash)),
+		}
+	}
+
 	pub async fn download(
 		&self,
 		client: &reqwest::Client,
 	) -> Result<Vec<u8>> {
+		if let Self::Attachment(a) = self {
+			return Ok(a.download().await?);
+		}
+		let url = self
+			.url()
+			.ok_or_else(|| anyhow!("The {} has no URL.", self.kind()))?;
+		let response =
+			client.get(url).send_measured().await?.error_for_status()?;
+		Ok(response.bytes().await?.to_vec())
+	}
+
+	pub fn filename(&self) -> String {
 		match self {
-			AttachmentOrThumbnail::Attachment(a) => Ok(a.download().await?),
-			AttachmentOrThumbnail::Embed(e) => {
-				// Download the image from the proxy URL.
-				let url = e.proxy_url.as_ref().ok_or_else(|| {
-					anyhow!("Embed thumbnail has no proxy URL")
-				})?;
-				let request = client.get(url).send().await?;
-				Ok(request.bytes().await?.to_vec())
+			Self::Attachment(a) => a.filename.clone(),
+			Self::Sticker(sticker) => {
+				format!("{}.{}", sticker.name, self.url_extension("png"))
+			}
+			Self::Emoji(emoji) => {
+				format!("{}.{}", emoji.name, self.url_extension("png"))
 			}
+			Self::Avatar(user) => {
+				format!("{}_avatar.{}", user.name, self.url_extension("png"))
+			}
+			Self::Banner(user) => {
+				format!("{}_banner.{}", user.name, self.url_extension("png"))
+			}
+			Self::Embed(_) => self.url_filename("thumbnail.png"),
+			Self::EmbedImage(_) => self.url_filename("image.png"),
+			Self::EmbedVideo(_) => self.url_filename("video.mp4"),
 		}
 	}
 
-	pub fn filename(&self) -> String {
+	/// What it is, such as `attachment` or `sticker`.
+	pub fn kind(&self) -> &'static str {
 		match self {
-			AttachmentOrThumbnail::Attachment(a) => a.filename.clone(),
-			AttachmentOrThumbnail::Embed(e) => {
-				// Parse the URL to get the filename.
-				let url = &e.proxy_url;
-				let url = if let Some(url) = url {
-					url::Url::parse(url).unwrap_or_else(|_| {
-						url::Url::parse("https://example.com/thumbnail.png")
-							.unwrap()
-					})
-				} else {
-					return "thumbnail.png".to_string();
-				};
-				if let Some(path_segments) = url.path_segments() {
-					let filename =
-						path_segments.last().unwrap_or("thumbnail.png");
-					filename.to_string()
-				} else {
-					"thumbnail.png".to_string()
-				}
+			Self::Attachment(_) => "attachment",
+			Self::Embed(_) => "embed thumbnail",
+			Self::EmbedImage(_) => "embed image",
+			Self::EmbedVideo(_) => "embed video",
+			Self::Sticker(_) => "sticker",
+			Self::Emoji(_) => "emoji",
+			Self::Avatar(_) => "avatar",
+			Self::Banner(_) => "banner",
+		}
+	}
+
+	/// The MIME type, such as `image/png`. Attachments use the type Discord
+	/// detected, and everything else goes by its format or extension.
+	pub fn mime_type(&self) -> String {
+		match self {
+			Self::Attachment(a) => match &a.content_type {
+				// Such as `text/plain; charset=utf-8`.
+				Some(content_type) => content_type
+					.split(';')
+					.next()
+					.unwrap_or_default()
+					.trim()
+					.to_string(),
+				None => mime_type_for_extension(&self.url_extension("")),
+			},
+			// APNG stickers are served as `.png`.
+			Self::Sticker(sticker)
+				if sticker.format_type == StickerFormatType::Apng =>
+			{
+				"image/apng".to_string()
 			}
+			_ => mime_type_for_extension(&self.url_extension("")),
+		}
+	}
+
+	/// The size in bytes, which only attachments know ahead of time.
+	pub fn size(&self) -> Option<u64> {
+		match self {
+			Self::Attachment(a) => Some(a.size as u64),
+			_ => None,
 		}
 	}
+
+	pub fn dimensions(&self) -> Option<(u32, u32)> {
+		match self {
+			Self::Attachment(a) => a.width.zip(a.height),
+			Self::Embed(e) => e.width.zip(e.height),
+			Self::EmbedImage(e) => e.width.zip(e.height),
+			Self::EmbedVideo(e) => e.width.zip(e.height),
+			Self::Sticker(_)
+			| Self::Emoji(_)
+			| Self::Avatar(_)
+			| Self::Banner(_) => None,
+		}
+	}
+
+	/// The last segment of the URL's path, or `fallback`.
+	fn url_filename(&self, fallback: &str) -> String {
+		self.url()
+			.and_then(|url| url::Url::parse(&url).ok())
+			.and_then(|url| {
+				url.path_segments()?
+					.next_back()
+					.filter(|segment| !segment.is_empty())
+					.map(str::to_string)
+			})
+			.unwrap_or_else(|| fallback.to_string())
+	}
+
+	fn url_extension(&self, fallback: &str) -> String {
+		let filename = match self {
+			Self::Attachment(a) => a.filename.clone(),
+			_ => self.url_filename(""),
+		};
+		Path::new(&filename)
+			.extension()
+			.map(|extension| extension.to_string_lossy().to_lowercase())
+			.unwrap_or_else(|| fallback.to_string())
+	}
+}
+
+const DISCORD_CDN: &str = "https://cdn.discordapp.com";
+const DISCORD_MEDIA_PROXY: &str = "https://media.discordapp.net";
+
+/// The largest PNG, or GIF when it's animated, of an avatar or banner.
+/// Serenity's URLs are WebPs at 1024 pixels.
+fn cdn_image_url(path: &str, user: &User, hash: &ImageHash) -> String {
+	let extension = if hash.is_animated() { "gif" } else { "png" };
+	format!(
+		"{}/{}/{}/{}.{}?size=4096",
+		DISCORD_CDN, path, user.id, hash, extension
+	)
+}
+
+/// The MIME type for a file extension, for when nothing better is known.
+fn mime_type_for_extension(extension: &str) -> String {
+	match extension.to_lowercase().as_str() {
+		"png" => "image/png",
+		"apng" => "image/apng",
+		"jpg" | "jpeg" | "jfif" => "image/jpeg",
+		"gif" => "image/gif",
+		"webp" => "image/webp",
+		"avif" => "image/avif",
+		"jxl" => "image/jxl",
+		"bmp" => "image/bmp",
+		"tif" | "tiff" => "image/tiff",
+		"svg" => "image/svg+xml",
+		"mp4" | "m4v" => "video/mp4",
+		"webm" => "video/webm",
+		"mov" => "video/quicktime",
+		"mkv" => "video/x-matroska",
+		"avi" => "video/x-msvideo",
+		"mp3" => "audio/mpeg",
+		"m4a" => "audio/mp4",
+		"ogg" | "oga" => "audio/ogg",
+		"opus" => "audio/opus",
+		"flac" => "audio/flac",
+		"wav" => "audio/wav",
+		"json" => "application/json",
+		_ => "application/octet-stream",
+	}
+	.to_string()
 }
 
 pub async fn is_file_larger_than_mb(
@@ -140,9 +437,13 @@ pub async fn is_file_larger_than_mb(
 	max_size_mb: u64,
 ) -> Result<(bool, u64)> {
 	let client = reqwest::Client::new();
-	let response = client.head(url).send().await?;
+	let response = client.head(url).send_measured().await?;
 
-	println!("{:#?}", response.headers());
+	tracing::debug!(
+		url = %logging::redact(url),
+		status = %response.status(),
+		"Checked the file size."
+	);
 
 	if let Some(content_length) =
 		response.headers().get(header::CONTENT_LENGTH)
@@ -184,3 +485,37 @@ pub fn escape_markdown(text: &str) -> String {
 	}
 	escaped
 }
+
+/// Such as `1h 2m 3s`, `2m 3s` or `3s`.
+pub fn format_duration(duration: Duration) -> String {
+	let seconds = duration.as_secs();
+	let (hours, minutes, seconds) =
+		(seconds / 3600, seconds / 60 % 60, seconds % 60);
+	if hours > 0 {
+		format!("{}h {}m {}s", hours, minutes, seconds)
+	} else if minutes > 0 {
+		format!("{}m {}s", minutes, seconds)
+	} else {
+		format!("{}s", seconds)
+	}
+}
+
+/// Such as `8.50 MiB`, the way Discord counts its upload limits.
+pub fn format_bytes(bytes: u64) -> String {
+	format!(
+		"{:.2}",
+		Byte::from_u64(bytes).get_appropriate_unit(UnitType::Binary)
+	)
+}
+
+/// Such as `` `1.20 MiB` → `819.20 KiB` (-33%) ``.
+pub fn format_size_change(before: u64, after: u64) -> String {
+	let sizes =
+		format!("`{}` → `{}`", format_bytes(before), format_bytes(after));
+	// An empty file has no percentage to change by.
+	if before == 0 {
+		return sizes;
+	}
+	let change = (after as f64 / before as f64 - 1.0) * 100.0;
+	format!("{} ({:+.0}%)", sizes, change)
+}
@@ -0,0 +1,151 @@
+use std::time::{Duration, Instant};
+
+use poise::{serenity_prelude::UserId, ChoiceParameter};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::{config::Context, logging::redact, storage::unix_now};
+
+/// Commands that aren't worth recording.
+const UNRECORDED_COMMANDS: &[&str] = &["history", "jobs"];
+
+#[derive(
+	Debug, Serialize, Deserialize, Clone, Copy, PartialEq, ChoiceParameter,
+)]
+pub enum Outcome {
+	Success,
+	Error,
+	Panic,
+}
+impl Outcome {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Success => "success",
+			Self::Error => "error",
+			Self::Panic => "panic",
+		}
+	}
+
+	pub fn parse(value: &str) -> Option<Self> {
+		match value {
+			"success" => Some(Self::Success),
+			"error" => Some(Self::Error),
+			"panic" => Some(Self::Panic),
+			_ => None,
+		}
+	}
+}
+
+/// A recorded command invocation.
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+	/// Set by the database.
+	pub id: i64,
+	pub user_id: UserId,
+	/// The name Discord shows, such as `ffmpeg` or `Convert to WebP`.
+	pub command: String,
+	/// The interaction's command data, which holds the arguments.
+	pub data: Value,
+	/// Unix seconds.
+	pub invoked_at: i64,
+	pub duration: Duration,
+	pub outcome: Outcome,
+	pub error: Option<String>,
+}
+impl HistoryEntry {
+	/// The arguments as `name: value` pairs, or the target for context menus.
+	pub fn arguments(&self) -> String {
+		if let Some(target_id) = self.data["target_id"].as_str() {
+			return format!("target: {}", target_id);
+		}
+		self.data["options"]
+			.as_array()
+			.map(|options| {
+				options
+					.iter()
+					.map(|option| {
+						let name = option["name"].as_str().unwrap_or("?");
+						let value = &option["value"];
+						// Attachments are stored by ID.
+						let attachment = value.as_str().and_then(|id| {
+							self.data["resolved"]["attachments"][id]
+								["filename"]
+								.as_str()
+						});
+						match (attachment, value) {
+							(Some(filename), _) => {
+								format!("{}: {}", name, filename)
+							}
+							(None, Value::String(value)) => {
+								format!("{}: {}", name, value)
+							}
+							(None, value) => format!("{}: {}", name, value),
+						}
+					})
+					.collect::<Vec<_>>()
+					.join(" ")
+			})
+			.unwrap_or_default()
+	}
+}
+
+/// When the command started, kept in the invocation data until it's
+/// recorded.
+struct Invocation {
+	started: Instant,
+	invoked_at: i64,
+}
+
+/// Runs before every command.
+pub async fn start(ctx: Context<'_>) {
+	ctx.set_invocation_data(Invocation {
+		started: Instant::now(),
+		invoked_at: unix_now(),
+	})
+	.await;
+}
+
+/// Saves the invocation to the database. Failing to save is only logged so
+/// that it never hides the command's own result.
+pub async fn record(
+	ctx: Context<'_>,
+	outcome: Outcome,
+	error: Option<String>,
+) {
+	let poise::Context::Application(app_ctx) = ctx else {
+		return;
+	};
+	if UNRECORDED_COMMANDS.contains(&ctx.command().identifying_name.as_str())
+	{
+		return;
+	}
+	let (duration, invoked_at) =
+		match ctx.invocation_data::<Invocation>().await {
+			Some(invocation) => {
+				(invocation.started.elapsed(), invocation.invoked_at)
+			}
+			None => (Duration::ZERO, unix_now()),
+		};
+	let data = match serde_json::to_value(&app_ctx.interaction.data) {
+		Ok(data) => data,
+		Err(e) => {
+			warn!("Failed to serialize the command's arguments: {}", e);
+			return;
+		}
+	};
+
+	let entry = HistoryEntry {
+		id: 0,
+		user_id: ctx.author().id,
+		command: app_ctx.interaction.data.name.clone(),
+		data,
+		invoked_at,
+		duration,
+		outcome,
+		error: error.map(|error| redact(&error)),
+	};
+	if let Err(e) = ctx.data().storage.add_history(&entry).await {
+		warn!("Failed to record the invocation: {:#}", e);
+	}
+}
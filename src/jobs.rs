@@ -0,0 +1,368 @@
+use std::{
+	convert::Infallible,
+	fmt,
+	future::{Future, IntoFuture},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Mutex,
+	},
+	time::Duration,
+};
+
+use anyhow::Result;
+use poise::{
+	serenity_prelude::{
+		ButtonStyle, ComponentInteractionCollector, CreateActionRow,
+		CreateAllowedMentions, CreateButton, CreateInteractionResponse,
+		UserId,
+	},
+	CreateReply, ReplyHandle,
+};
+use tokio::sync::{watch, Notify};
+use tracing::warn;
+
+use crate::{
+	config::Context, helpers::format_duration, metrics, storage::unix_now,
+};
+
+/// How often the status is edited with new progress. Discord rate limits
+/// edits, so more often wouldn't show up anyway.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(3);
+/// The number of characters in the progress bar.
+const PROGRESS_BAR_WIDTH: usize = 20;
+
+tokio::task_local! {
+	/// Where the running job's progress goes, set by [`run`].
+	static PROGRESS: watch::Sender<Option<Progress>>;
+}
+
+/// Jobs in the same class compete for the same slots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobClass {
+	/// ffmpeg and compression.
+	Video,
+	/// Image encoders such as cjxl and img2webp.
+	Image,
+	/// Downloaders such as yt-dlp.
+	Download,
+}
+impl JobClass {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Video => "video",
+			Self::Image => "image",
+			Self::Download => "download",
+		}
+	}
+}
+
+/// A job that's running or waiting for a slot.
+#[derive(Debug, Clone)]
+pub struct JobInfo {
+	pub id: u64,
+	pub class: JobClass,
+	pub user_id: UserId,
+	/// The name Discord shows, such as `ffmpeg` or `Convert To WebP`.
+	pub command: String,
+	/// Unix seconds.
+	pub queued_at: i64,
+	/// Unix seconds, or `None` while it's waiting.
+	pub started_at: Option<i64>,
+}
+
+/// How far along a running job is.
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+	/// From 0 to 1, or `None` if the total isn't known.
+	pub fraction: Option<f64>,
+	/// How far into the media the job is.
+	pub position: Duration,
+	/// How many times faster than real time it's going.
+	pub speed: Option<f64>,
+	pub eta: Option<Duration>,
+}
+
+/// Shows the progress on the job's status if it's running inside [`run`].
+/// Anywhere else it does nothing, so any transcode can call it.
+pub fn report_progress(progress: Progress) {
+	let _ = PROGRESS.try_with(|sender| sender.send_replace(Some(progress)));
+}
+
+/// Every job that's running or waiting, in the order they were queued.
+#[derive(Debug, Default)]
+pub struct Jobs {
+	jobs: Mutex<Vec<JobInfo>>,
+	/// Woken whenever a job starts or finishes.
+	changed: Notify,
+	next_id: AtomicU64,
+}
+
+impl Jobs {
+	pub fn list(&self) -> Vec<JobInfo> {
+		self.jobs.lock().unwrap_or_else(|e| e.into_inner()).clone()
+	}
+
+	fn enqueue(
+		&self,
+		class: JobClass,
+		user_id: UserId,
+		command: String,
+	) -> QueuedJob<'_> {
+		let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+		self.jobs
+			.lock()
+			.unwrap_or_else(|e| e.into_inner())
+			.push(JobInfo {
+				id,
+				class,
+				user_id,
+				command,
+				queued_at: unix_now(),
+				started_at: None,
+			});
+		QueuedJob { jobs: self, id }
+	}
+
+	/// Starts the job if its class has a free slot and nothing was queued
+	/// before it. Otherwise returns its place in line.
+	fn try_start(&self, id: u64, limit: usize) -> Result<(), usize> {
+		let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+		let Some(class) =
+			jobs.iter().find(|job| job.id == id).map(|job| job.class)
+		else {
+			return Ok(());
+		};
+		let running = jobs
+			.iter()
+			.filter(|job| job.class == class && job.started_at.is_some())
+			.count();
+		let ahead = jobs
+			.iter()
+			.take_while(|job| job.id != id)
+			.filter(|job| job.class == class && job.started_at.is_none())
+			.count();
+		if running >= limit || ahead > 0 {
+			return Err(ahead + 1);
+		}
+
+		if let Some(job) = jobs.iter_mut().find(|job| job.id == id) {
+			job.started_at = Some(unix_now());
+		}
+		drop(jobs);
+		self.changed.notify_waiters();
+		Ok(())
+	}
+
+	fn remove(&self, id: u64) {
+		self.jobs
+			.lock()
+			.unwrap_or_else(|e| e.into_inner())
+			.retain(|job| job.id != id);
+		self.changed.notify_waiters();
+	}
+}
+
+/// Removes the job from the list when it finishes, fails or is dropped.
+struct QueuedJob<'a> {
+	jobs: &'a Jobs,
+	id: u64,
+}
+impl Drop for QueuedJob<'_> {
+	fn drop(&mut self) {
+		self.jobs.remove(self.id);
+	}
+}
+
+/// Returned when the job was cancelled with its Cancel button. The reply
+/// already says so, so the error handler doesn't report it again.
+#[derive(Debug)]
+pub struct Cancelled;
+impl fmt::Display for Cancelled {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "The job was cancelled.")
+	}
+}
+impl std::error::Error for Cancelled {}
+
+/// Runs `job` once a slot in its class is free.
+///
+/// Until it finishes, the reply shows its place in line or that it's
+/// running, along with a Cancel button and whatever progress the job
+/// reports with [`report_progress`]. Cancelling drops `job`, which kills any
+/// process it started. The status is deleted afterwards so the command can
+/// send its result as usual.
+pub async fn run<T>(
+	ctx: Context<'_>,
+	class: JobClass,
+	ephemeral: bool,
+	job: impl Future<Output = Result<T>>,
+) -> Result<T> {
+	let jobs = &ctx.data().jobs;
+	let queued = jobs.enqueue(
+		class,
+		ctx.author().id,
+		metrics::command_label(ctx.command()),
+	);
+	let cancel_id = format!("cancel_job:{}", queued.id);
+
+	let limit = ctx.data().config().job_concurrency.limit(class);
+	let position = jobs.try_start(queued.id, limit).err();
+	let status = ctx
+		.send(
+			status_reply(queue_text(class, position), &cancel_id)
+				.ephemeral(ephemeral),
+		)
+		.await?;
+
+	let cancel =
+		ComponentInteractionCollector::new(&ctx.serenity_context().shard)
+			.author_id(ctx.author().id)
+			.custom_ids(vec![cancel_id.clone()])
+			.into_future();
+
+	let (progress_sender, progress) = watch::channel(None);
+	let job = PROGRESS.scope(progress_sender, job);
+
+	let result = tokio::select! {
+		result = async {
+			if position.is_some() {
+				wait_for_turn(
+					ctx, &queued, class, position, &status, &cancel_id,
+				)
+				.await;
+			}
+			tokio::select! {
+				result = job => result,
+				never = show_progress(ctx, progress, &status, &cancel_id)
+					=> match never {},
+			}
+		} => result,
+		Some(interaction) = cancel => {
+			interaction
+				.create_response(ctx, CreateInteractionResponse::Acknowledge)
+				.await?;
+			status
+				.edit(
+					ctx,
+					CreateReply::default()
+						.content("Cancelled.")
+						.components(vec![]),
+				)
+				.await?;
+			return Err(Cancelled.into());
+		}
+	};
+
+	if let Err(e) = status.delete(ctx).await {
+		warn!("Failed to delete a job's status: {}", e);
+	}
+	result
+}
+
+/// Keeps the reply's place in line up to date until the job starts.
+async fn wait_for_turn(
+	ctx: Context<'_>,
+	queued: &QueuedJob<'_>,
+	class: JobClass,
+	mut shown: Option<usize>,
+	status: &ReplyHandle<'_>,
+	cancel_id: &str,
+) {
+	loop {
+		// Created before checking so a change in between isn't missed.
+		let changed = queued.jobs.changed.notified();
+		let limit = ctx.data().config().job_concurrency.limit(class);
+		let position = queued.jobs.try_start(queued.id, limit).err();
+		if position != shown {
+			if let Err(e) = status
+				.edit(
+					ctx,
+					status_reply(queue_text(class, position), cancel_id),
+				)
+				.await
+			{
+				warn!("Failed to show a job's place in line: {}", e);
+			}
+			shown = position;
+		}
+		if position.is_none() {
+			return;
+		}
+		changed.await;
+	}
+}
+
+/// Edits the status whenever the job reports progress, at most once every
+/// [`PROGRESS_INTERVAL`]. Runs until the job finishes and drops it.
+async fn show_progress(
+	ctx: Context<'_>,
+	mut progress: watch::Receiver<Option<Progress>>,
+	status: &ReplyHandle<'_>,
+	cancel_id: &str,
+) -> Infallible {
+	while progress.changed().await.is_ok() {
+		let Some(latest) = progress.borrow_and_update().clone() else {
+			continue;
+		};
+		if let Err(e) = status
+			.edit(ctx, status_reply(progress_text(&latest), cancel_id))
+			.await
+		{
+			warn!("Failed to show a job's progress: {}", e);
+		}
+		tokio::time::sleep(PROGRESS_INTERVAL).await;
+	}
+	// The job dropped its sender, so it's about to finish.
+	std::future::pending().await
+}
+
+fn queue_text(class: JobClass, position: Option<usize>) -> String {
+	match position {
+		Some(position) => {
+			format!("#{} in the {} queue…", position, class.name())
+		}
+		None => "Running…".to_string(),
+	}
+}
+
+/// A progress bar with the percentage, speed and time left, such as
+/// `[██████░░░░] 60%, 2.5x, about 1m 20s left`. Without a total it only
+/// shows how far it's gotten.
+fn progress_text(progress: &Progress) -> String {
+	let mut parts = Vec::new();
+	match progress.fraction {
+		Some(fraction) => {
+			let fraction = fraction.clamp(0.0, 1.0);
+			let filled =
+				(fraction * PROGRESS_BAR_WIDTH as f64).round() as usize;
+			parts.push(format!(
+				"`[{}{}]` {:.0}%",
+				"█".repeat(filled),
+				"░".repeat(PROGRESS_BAR_WIDTH - filled),
+				fraction * 100.0
+			));
+		}
+		None => parts.push(format!(
+			"Processed {}",
+			format_duration(progress.position)
+		)),
+	}
+	if let Some(speed) = progress.speed {
+		parts.push(format!("{:.1}x", speed));
+	}
+	if let Some(eta) = progress.eta {
+		parts.push(format!("about {} left", format_duration(eta)));
+	}
+	parts.join(", ")
+}
+
+fn status_reply(content: impl Into<String>, cancel_id: &str) -> CreateReply {
+	CreateReply::default()
+		.allowed_mentions(CreateAllowedMentions::default())
+		.content(content)
+		.components(vec![CreateActionRow::Buttons(vec![CreateButton::new(
+			cancel_id,
+		)
+		.label("Cancel")
+		.style(ButtonStyle::Danger)])])
+}
@@ -0,0 +1,113 @@
+use std::{
+	sync::{LazyLock, RwLock},
+	time::Instant,
+};
+
+use poise::serenity_prelude::{
+	self as serenity, async_trait, FullEvent, Interaction,
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tracing::{field, info, info_span, Instrument};
+use tracing_subscriber::EnvFilter;
+
+use crate::{config::Config, metrics};
+
+const REDACTED: &str = "[redacted]";
+
+/// Matches the query string or fragment of a URL, which is where API keys
+/// and user input usually end up.
+static URL_QUERY_REGEX: LazyLock<Regex> =
+	LazyLock::new(|| Regex::new(r"(https?://[^\s?#]+)([?#])[^\s]*").unwrap());
+
+/// The config values that must never show up in the logs.
+static SECRETS: LazyLock<RwLock<Vec<String>>> =
+	LazyLock::new(|| RwLock::new(Vec::new()));
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum LogFormat {
+	#[default]
+	Pretty,
+	Json,
+}
+
+/// Sets up the global subscriber. `RUST_LOG` overrides the default `info`
+/// level.
+pub fn init(format: LogFormat) {
+	let filter = EnvFilter::try_from_default_env()
+		.unwrap_or_else(|_| EnvFilter::new("info"));
+	let builder = tracing_subscriber::fmt().with_env_filter(filter);
+	match format {
+		LogFormat::Pretty => builder.pretty().init(),
+		LogFormat::Json => builder
+			.json()
+			.with_current_span(true)
+			.with_span_list(true)
+			.init(),
+	}
+}
+
+/// Remembers the secrets in the config so that `redact` can remove them.
+/// Called again whenever the config reloads.
+pub fn set_secrets(config: &Config) {
+	let secrets = [
+		Some(&config.discord_token),
+		config.wolfram_alpha_full_app_id.as_ref(),
+		config.randomorg_api_key.as_ref(),
+	]
+	.into_iter()
+	.flatten()
+	.filter(|secret| !secret.trim().is_empty())
+	.cloned()
+	.collect();
+	*SECRETS.write().unwrap_or_else(|e| e.into_inner()) = secrets;
+}
+
+/// Removes known secrets and URL query strings from text that's about to
+/// be logged.
+pub fn redact(text: &str) -> String {
+	let mut text = URL_QUERY_REGEX
+		.replace_all(text, format!("$1$2{}", REDACTED))
+		.into_owned();
+	for secret in SECRETS.read().unwrap_or_else(|e| e.into_inner()).iter() {
+		text = text.replace(secret.as_str(), REDACTED);
+	}
+	text
+}
+
+/// Wraps the poise framework so that every command invocation runs inside
+/// its own span and is counted in the metrics.
+pub struct TracedFramework<F>(pub F);
+
+#[async_trait]
+impl<F: serenity::Framework> serenity::Framework for TracedFramework<F> {
+	async fn init(&mut self, client: &serenity::Client) {
+		self.0.init(client).await;
+	}
+
+	async fn dispatch(&self, ctx: serenity::Context, event: FullEvent) {
+		let (name, span) = match &event {
+			FullEvent::InteractionCreate {
+				interaction: Interaction::Command(interaction),
+			} => (
+				interaction.data.name.clone(),
+				info_span!(
+					"command",
+					name = %interaction.data.name,
+					user = %interaction.user.name,
+					user_id = %interaction.user.id,
+					interaction_id = %interaction.id,
+					duration_ms = field::Empty,
+				),
+			),
+			_ => return self.0.dispatch(ctx, event).await,
+		};
+
+		let start = Instant::now();
+		self.0.dispatch(ctx, event).instrument(span.clone()).await;
+		let duration = start.elapsed();
+		metrics::observe_command(&name, duration);
+		span.record("duration_ms", duration.as_millis() as u64);
+		info!(parent: &span, "Command finished.");
+	}
+}
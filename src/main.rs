@@ -1,67 +1,121 @@
+use anyhow::Result;
+use config::{config_path_from_args, BotData, Config};
+use logging::TracedFramework;
 use poise::serenity_prelude as serenity;
-use config::{BotData, Config};
+use registration::Scope;
+use storage::Storage;
+use tracing::info;
 
 pub mod commands;
+pub mod config;
+pub mod convert;
+pub mod error;
 pub mod helpers;
+pub mod history;
+pub mod jobs;
+pub mod logging;
 pub mod media;
+pub mod metrics;
 pub mod os_command;
-pub mod config;
+pub mod picker;
+pub mod probe;
+pub mod profile;
+pub mod registration;
+pub mod sandbox;
+pub mod sniff;
+pub mod storage;
+pub mod workspace;
 
 #[tokio::main]
-async fn main() {
-	let config = Config::new();
+async fn main() -> Result<()> {
+	error::install_panic_hook();
+
+	let config_path = config_path_from_args()?;
+	let config = Config::load(&config_path)?;
+	logging::init(config.log_format);
+	logging::set_secrets(&config);
+	os_command::set_timeouts(&config);
+	sandbox::set_limits(&config);
+	// Fail before connecting to Discord rather than run without storage.
+	let storage = Storage::open(&config.database_path).await?;
+	info!("Opened the database at {}.", storage.path().display());
+	if let Some(address) = config.metrics_address {
+		metrics::serve(address).await?;
+	}
+	let discord_token = config.discord_token.clone();
 	let intents = serenity::GatewayIntents::non_privileged();
 
 	let framework = poise::Framework::builder()
 		.options(poise::FrameworkOptions {
 			owners: config.owner_ids.clone(),
-			commands: vec![
-				commands::age(),
-				commands::github(),
-				commands::fix(),
-				commands::uptime(),
-				commands::help(),
-				commands::snowstamp(),
-				commands::wolfram(),
-				commands::wayback(),
-				commands::unicode(),
-				commands::escape(),
-				commands::roll(),
-				commands::youtube(),
-				commands::ocr(),
-				commands::bible(),
-				commands::favoritize(),
-				commands::favoritize_context_menu(),
-				commands::webp(),
-				commands::webp_context_menu(),
-				commands::jxl(),
-				commands::jxl_context_menu(),
-				commands::ffmpeg(),
-				commands::translate(),
-				commands::translate_context_menu(),
-				commands::embed(),
-				commands::screenshot(),
-				commands::flip(),
-				commands::now_playing(),
-				commands::song_info(),
-			],
+			commands: commands::enabled_commands(&config),
+			on_error: |error| Box::pin(error::on_error(error)),
+			pre_command: |ctx| Box::pin(history::start(ctx)),
+			post_command: |ctx| {
+				Box::pin(history::record(
+					ctx,
+					history::Outcome::Success,
+					None,
+				))
+			},
 			..Default::default()
 		})
-		.setup(|ctx, _ready, framework| {
+		.setup(move |ctx, _ready, framework| {
 			Box::pin(async move {
-				poise::builtins::register_globally(
-					ctx,
+				let scope = Scope::from_config(&config);
+				if registration::sync(
+					&ctx.http,
 					&framework.options().commands,
+					scope,
 				)
-				.await?;
-				Ok(BotData::new())
+				.await?
+				{
+					info!("Registered commands {}.", scope);
+				} else {
+					info!("Commands are already up to date.");
+				}
+				let data = BotData::new(config, storage).await?;
+				data.watch_config(config_path, ctx.http.clone());
+				Ok(data)
 			})
 		})
 		.build();
 
-	let client =
-		serenity::ClientBuilder::new(config.discord_token.clone(), intents)
-			.framework(framework)
-			.await;
-	client.unwrap().start().await.unwrap();
+	let mut client = serenity::ClientBuilder::new(discord_token, intents)
+		.framework(TracedFramework(framework))
+		.await?;
+
+	let shard_manager = client.shard_manager.clone();
+	tokio::spawn(async move {
+		wait_for_shutdown_signal().await;
+		info!("Shutting down.");
+		os_command::shutdown();
+		shard_manager.shutdown_all().await;
+	});
+
+	client.start().await?;
+	Ok(())
+}
+
+/// Ctrl+C, or SIGTERM from Docker.
+async fn wait_for_shutdown_signal() {
+	#[cfg(unix)]
+	{
+		use tokio::signal::unix::{signal, SignalKind};
+		match signal(SignalKind::terminate()) {
+			Ok(mut terminate) => {
+				tokio::select! {
+					_ = tokio::signal::ctrl_c() => {}
+					_ = terminate.recv() => {}
+				}
+			}
+			Err(_) => {
+				tokio::signal::ctrl_c().await.ok();
+			}
+		}
+	}
+	#[cfg(not(unix))]
+	{
+		tokio::signal::ctrl_c().await.ok();
+	}
 }
@@ -1,350 +1,873 @@
-use core::str;
-use std::io::{Read, Write};
-use std::process::Command;
-use std::{fs::File, path::Path};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::time::Duration;
 
 use anyhow::{anyhow, Result};
-use byte_unit::Byte;
-use inline_format::format_args;
-use tempfile::NamedTempFile;
+use tracing::debug;
+
+use crate::{
+	helpers::format_bytes,
+	jobs::{self, Progress},
+	os_command::run_os_command_with_stdout,
+	probe::{MediaInfo, MediaType, StreamInfo, StreamKind},
+	sandbox,
+	workspace::Workspace,
+};
+
+/// An ffmpeg command that reports its progress on stdout for
+/// [`run_ffmpeg_with_progress`], within the configured `ffmpeg_limits`.
+/// Inputs should be added with [`ffmpeg_input`].
+pub fn ffmpeg_command() -> Command {
+	let mut command = Command::new("ffmpeg");
+	command.args(["-hide_banner", "-nostats", "-progress", "pipe:1"]);
+	sandbox::limit_resources(&mut command, &sandbox::limits());
+	command
+}
 
-#[derive(Debug)]
-pub struct CompressedFile {
-	pub file: NamedTempFile,
-	pub scale: f64,
-	pub quality: u32,
+/// The arguments that read `path` as an input. Only local files and pipes
+/// can be opened, even by a playlist inside it, and `file:` keeps the path
+/// from being read as a protocol.
+pub fn ffmpeg_input(path: impl AsRef<Path>) -> Vec<OsString> {
+	let mut file = OsString::from("file:");
+	file.push(path.as_ref());
+	vec![
+		"-protocol_whitelist".into(),
+		sandbox::ALLOWED_PROTOCOLS.join(",").into(),
+		"-i".into(),
+		file,
+	]
 }
 
-pub fn compress_file(input: &Path) -> Result<(NamedTempFile, String)> {
-	let media_type = determine_media_type(input);
+/// Runs a command from [`ffmpeg_command`], reporting its progress to the job
+/// it's part of. `duration` is how long the input is, if it's known, which
+/// is needed for the percentage and the time left.
+pub async fn run_ffmpeg_with_progress(
+	command: Command,
+	duration: Option<Duration>,
+) -> Result<Output> {
+	run_ffmpeg_pass(command, duration, 0, 1).await
+}
 
-	println!("Media type: {:?}", media_type);
+/// Like [`run_ffmpeg_with_progress`] for pass `pass` (from 0) of `passes`
+/// over the same input, so the progress covers every pass instead of
+/// starting over for each one.
+async fn run_ffmpeg_pass(
+	command: Command,
+	duration: Option<Duration>,
+	pass: u32,
+	passes: u32,
+) -> Result<Output> {
+	let mut progress = FfmpegProgress::default();
+	let output = run_os_command_with_stdout("ffmpeg", command, |line| {
+		let Some(mut progress) = progress.parse_line(line, duration) else {
+			return;
+		};
+		let remaining_passes = passes - pass - 1;
+		progress.fraction = progress
+			.fraction
+			.map(|fraction| (pass as f64 + fraction) / passes as f64);
+		progress.eta = progress.eta.zip(duration).zip(progress.speed).map(
+			|((eta, duration), speed)| {
+				eta + (duration * remaining_passes).div_f64(speed)
+			},
+		);
+		jobs::report_progress(progress);
+	})
+	.await?;
 
-	match media_type {
-		MediaType::Image => {
-			Ok((compress_image(input, 0)?, "webp".to_string()))
-		}
-		MediaType::Video => {
-			Ok((compress_video(input, 0)?, "mp4".to_string()))
-		}
-		// MediaType::Audio => {
-		// 	Ok((compress_audio(input, 0)?, "mp3".to_string()))
-		// }
-		_ => Err(anyhow!("Unsupported file type.")),
+	if let Some(reason) =
+		sandbox::limit_exceeded(&output.status, &sandbox::limits())
+	{
+		return Err(anyhow!(reason));
 	}
+	Ok(output)
 }
 
-#[derive(Debug)]
-enum MediaType {
-	Image,
-	Video,
-	// Audio,
-	Unknown,
+/// Collects the `key=value` lines from `-progress` until the end of each
+/// block.
+#[derive(Debug, Default)]
+struct FfmpegProgress {
+	out_time: Duration,
+	speed: Option<f64>,
 }
-
-fn determine_media_type(file_path: &Path) -> MediaType {
-	let output = Command::new("ffprobe")
-		.args([
-			"-v",
-			"error",
-			"-show_entries",
-			"format=format_name",
-			"-of",
-			"default=noprint_wrappers=1:nokey=1",
-			file_path.to_str().unwrap(),
-		])
-		.output();
-
-	if let Ok(output) = output {
-		if output.status.success() {
-			if let Ok(format) = str::from_utf8(&output.stdout) {
-				let format = format.trim();
-
-				println!("Format: {}", format);
-
-				// List of known image formats
-				let image_formats = [
-					"jpeg", "jpg", "png", "bmp", "gif", "tiff", "webp",
-					"jxl", "heic", "heif", "avif", "svg",
-				];
-
-				// List of known video formats
-				let video_formats = [
-					"mp4", "mkv", "mov", "avi", "flv", "wmv", "webm", "mpeg",
-				];
-
-				if image_formats.iter().any(|&f| format.contains(f)) {
-					return MediaType::Image;
-				} else if video_formats.iter().any(|&f| format.contains(f)) {
-					return MediaType::Video;
+impl FfmpegProgress {
+	/// Returns the progress once a block ends with `progress=continue` or
+	/// `progress=end`.
+	fn parse_line(
+		&mut self,
+		line: &str,
+		duration: Option<Duration>,
+	) -> Option<Progress> {
+		let (key, value) = line.split_once('=')?;
+		let value = value.trim();
+		match key.trim() {
+			// Both are in microseconds despite the name. Either can be
+			// `N/A` before the first frame.
+			"out_time_us" | "out_time_ms" => {
+				if let Ok(micros) = value.parse() {
+					self.out_time = Duration::from_micros(micros);
 				}
 			}
+			"speed" => {
+				self.speed = value
+					.trim_end_matches('x')
+					.parse::<f64>()
+					.ok()
+					.filter(|speed| *speed > 0.0);
+			}
+			"progress" => return Some(self.progress(duration)),
+			_ => {}
 		}
+		None
 	}
 
-	MediaType::Unknown
-}
-
-const TARGET_SIZE_MB: u64 = 8;
-fn mb_to_bytes(mb: u64) -> u64 {
-	mb * 1024 * 1024
+	fn progress(&self, duration: Option<Duration>) -> Progress {
+		let duration = duration.filter(|duration| !duration.is_zero());
+		Progress {
+			fraction: duration.map(|duration| {
+				self.out_time.as_secs_f64() / duration.as_secs_f64()
+			}),
+			position: self.out_time,
+			speed: self.speed,
+			eta: duration.zip(self.speed).map(|(duration, speed)| {
+				duration.saturating_sub(self.out_time).div_f64(speed)
+			}),
+		}
+	}
 }
 
-fn compress_video(input: &Path, attempt: u8) -> Result<NamedTempFile> {
-	if !input.exists() {
-		return Err(anyhow!(
-			"Input file does not exist: {}",
-			input.display()
+/// Extensions Discord shows a player for. It goes by the name first, so a
+/// playable stream in an `.mkv` still shows up as a download.
+const INLINE_VIDEO_EXTENSIONS: &[&str] = &["mp4", "m4v", "mov", "webm"];
+const INLINE_AUDIO_EXTENSIONS: &[&str] =
+	&["mp3", "ogg", "oga", "opus", "wav", "flac", "m4a", "aac"];
+const INLINE_IMAGE_EXTENSIONS: &[&str] =
+	&["png", "jpg", "jpeg", "gif", "webp", "avif"];
+/// What the desktop, web and mobile clients can all decode.
+const INLINE_VIDEO_CODECS: &[&str] = &["h264", "vp8", "vp9", "av1"];
+const INLINE_AUDIO_CODECS: &[&str] = &[
+	"aac",
+	"mp3",
+	"opus",
+	"vorbis",
+	"flac",
+	"pcm_s16le",
+	"pcm_s24le",
+];
+
+/// The reasons Discord clients wouldn't play or show the file inline, or
+/// nothing if they would.
+pub fn inline_playback_problems(
+	info: &MediaInfo,
+	filename: &str,
+) -> Vec<String> {
+	let extension = Path::new(filename)
+		.extension()
+		.map(|extension| extension.to_string_lossy().to_lowercase())
+		.unwrap_or_default();
+	let codec = |stream: &StreamInfo| {
+		stream
+			.codec
+			.clone()
+			.unwrap_or_else(|| "unknown".to_string())
+	};
+
+	let mut problems = Vec::new();
+	let allowed_extensions = match info.media_type() {
+		MediaType::Image => INLINE_IMAGE_EXTENSIONS,
+		MediaType::Audio => INLINE_AUDIO_EXTENSIONS,
+		MediaType::Video => INLINE_VIDEO_EXTENSIONS,
+		MediaType::Unknown => {
+			return vec!["ffprobe didn't find any audio or video.".to_string()]
+		}
+	};
+	if !allowed_extensions.contains(&extension.as_str()) {
+		problems.push(format!(
+			"Discord doesn't embed `.{}` files. It only embeds {}.",
+			extension,
+			allowed_extensions
+				.iter()
+				.map(|extension| format!("`.{}`", extension))
+				.collect::<Vec<_>>()
+				.join(", ")
 		));
 	}
-	if attempt >= 2 {
-		return Err(anyhow!("Ran out of attempts while compressing video."));
-	}
-
-	let mut input_file = File::open(input)?;
 
-	let target_size_adjusted =
-		mb_to_bytes(TARGET_SIZE_MB - attempt as u64 - 1);
-
-	println!(
-		"Target size adjusted: {}",
-		Byte::from_u64(target_size_adjusted)
-	);
-
-	// If the file's already small enough, don't bother converting it.
-	if input_file.metadata()?.len() <= target_size_adjusted {
-		println!("File is already small enough.");
-		return file_to_named_temp_file(&mut input_file);
+	if info.media_type() == MediaType::Video {
+		if let Some(video) = info.video_stream() {
+			if !INLINE_VIDEO_CODECS.contains(&codec(video).as_str()) {
+				problems.push(format!(
+					"The video is {}, but clients only decode H.264, VP8, VP9 \
+					 and AV1.",
+					codec(video)
+				));
+			}
+			// Browsers can't decode 10-bit H.264 or 4:2:2 and 4:4:4 in
+			// general.
+			if let Some(pixel_format) = video.pixel_format.as_deref() {
+				if !matches!(pixel_format, "yuv420p" | "yuvj420p") {
+					problems.push(format!(
+						"The pixel format is {}, but most clients need \
+						 yuv420p.",
+						pixel_format
+					));
+				}
+			}
+		}
+		if extension == "webm" {
+			let not_webm = info.streams.iter().find(|stream| {
+				matches!(stream.kind, StreamKind::Video | StreamKind::Audio)
+					&& !matches!(
+						codec(stream).as_str(),
+						"vp8" | "vp9" | "av1" | "opus" | "vorbis"
+					)
+			});
+			if let Some(stream) = not_webm {
+				problems.push(format!(
+					"WebM can't hold {}, so browsers won't play it.",
+					codec(stream)
+				));
+			}
+		}
 	}
 
-	let input_path = input.to_str().unwrap();
-
-	let output = NamedTempFile::new()?;
-	let output_path = output.path().to_str().unwrap();
-
-	println!("Converting video.");
-
-	let mut ffmpeg_command = Command::new("ffmpeg");
-
-	ffmpeg_command.args([
-		"-y", "-i", input_path, "-c:v", "libx265", "-preset", "medium", "-f",
-		"mp4",
-	]);
-
-	println!("Compressing video.");
-	let video_info = get_video_info(input)?;
-
-	let new_bitrate = estimate_video_bitrate(
-		video_info.video_bitrate,
-		video_info.audio_bitrate,
-		video_info.duration,
-		target_size_adjusted * 8, // Convert to bits
-	);
-	if new_bitrate < video_info.video_bitrate {
-		println!("Reducing FPS to 30.");
-		ffmpeg_command.args(["-vf", &format!("fps={}", 30)]);
+	if info.media_type() != MediaType::Image {
+		if let Some(audio) = info.audio_stream() {
+			if !INLINE_AUDIO_CODECS.contains(&codec(audio).as_str()) {
+				problems.push(format!(
+					"The audio is {}, which clients can't decode.",
+					codec(audio)
+				));
+			}
+		}
 	}
 
-	let new_bitrate = format!("{}k", new_bitrate / 1024);
-
-	println!(
-		"Old bitrate: {}",
-		format_args!("{}k", video_info.video_bitrate / 1024)
-	);
-	println!("New bitrate: {}", new_bitrate);
-
-	ffmpeg_command.args(["-b:v", &new_bitrate]);
+	problems
+}
 
-	ffmpeg_command.arg(output_path);
+/// What compression and Make Playable write. Every client plays these in
+/// an MP4, unlike VP9 or HEVC.
+const PLAYABLE_VIDEO_CODECS: &[&str] = &["h264", "av1"];
+const PLAYABLE_AUDIO_CODECS: &[&str] = &["aac", "opus"];
+/// 8-bit 4:2:0 H.264 in the High profile, which every browser can decode.
+const PLAYABLE_H264_ARGS: &[&str] = &[
+	"-c:v",
+	"libx264",
+	"-preset",
+	"medium",
+	"-profile:v",
+	"high",
+	"-pix_fmt",
+	"yuv420p",
+];
+/// Puts the index first so clients can start playing before the whole file
+/// has downloaded.
+const PLAYABLE_MP4_ARGS: &[&str] = &["-movflags", "+faststart", "-f", "mp4"];
+/// The quality used when the video has to be re-encoded without a size
+/// target. Lower is better.
+const PLAYABLE_CRF: &str = "20";
+const PLAYABLE_AUDIO_BITRATE: &str = "160k";
+
+/// Writes a faststart MP4 that Discord plays inline into the workspace's
+/// outputs and returns its path.
+///
+/// Streams that are already H.264 or AV1 in yuv420p, or AAC or Opus, are
+/// copied as they are, so a file that only needs a new container is
+/// remuxed without losing any quality.
+pub async fn make_playable(
+	input: &Path,
+	info: &MediaInfo,
+	workspace: &Workspace,
+) -> Result<PathBuf> {
+	let video = info
+		.video_stream()
+		.filter(|_| info.media_type() == MediaType::Video)
+		.ok_or_else(|| anyhow!("There's no video to make playable."))?;
+	let output = workspace.output_path("playable.mp4");
+
+	let mut ffmpeg_command = ffmpeg_command();
+	ffmpeg_command
+		.current_dir(workspace.path())
+		.args(["-y", "-nostdin"])
+		.args(ffmpeg_input(input))
+		.args(["-map", "0:v:0"]);
+
+	let video_codec = video.codec.as_deref().unwrap_or_default();
+	let pixel_format = video.pixel_format.as_deref().unwrap_or_default();
+	if PLAYABLE_VIDEO_CODECS.contains(&video_codec)
+		&& matches!(pixel_format, "yuv420p" | "yuvj420p")
+	{
+		ffmpeg_command.args(["-c:v", "copy"]);
+	} else {
+		ffmpeg_command
+			.args(["-vf", "scale=trunc(iw/2)*2:trunc(ih/2)*2"])
+			.args(PLAYABLE_H264_ARGS)
+			.args(["-crf", PLAYABLE_CRF]);
+	}
 
-	let ffmpeg_output = ffmpeg_command
-		.output()
-		.map_err(|e| anyhow!("Failed to execute FFmpeg: {}", e))?;
+	match info.audio_stream() {
+		Some(audio) => {
+			let audio_codec = audio.codec.as_deref().unwrap_or_default();
+			ffmpeg_command.args(["-map", "0:a:0"]);
+			if PLAYABLE_AUDIO_CODECS.contains(&audio_codec) {
+				ffmpeg_command.args(["-c:a", "copy"]);
+			} else {
+				ffmpeg_command.args([
+					"-c:a",
+					"aac",
+					"-b:a",
+					PLAYABLE_AUDIO_BITRATE,
+				]);
+			}
+		}
+		None => {
+			ffmpeg_command.arg("-an");
+		}
+	}
+	ffmpeg_command.args(PLAYABLE_MP4_ARGS).arg(&output);
 
+	let ffmpeg_output =
+		run_ffmpeg_with_progress(ffmpeg_command, info.duration).await?;
 	if !ffmpeg_output.status.success() {
 		return Err(anyhow!(
-			"Compression failed: {}",
+			"```\n{}\n```",
 			String::from_utf8_lossy(&ffmpeg_output.stderr)
 		));
 	}
+	Ok(output)
+}
+
+#[derive(Debug)]
+pub struct CompressedFile {
+	pub path: PathBuf,
+	pub scale: f64,
+	pub quality: u32,
+}
+
+/// Writes a copy of the file that fits in `target_size` bytes into the
+/// workspace's outputs and returns its path and extension. `info` is the
+/// input's [`crate::probe::probe`].
+pub async fn compress_file(
+	input: &Path,
+	info: &MediaInfo,
+	workspace: &Workspace,
+	target_size: u64,
+) -> Result<(PathBuf, String)> {
+	let media_type = info.media_type();
+
+	debug!(
+		?media_type,
+		target_size = %format_bytes(target_size),
+		"Compressing {}.",
+		input.display()
+	);
 
-	let output_file_size = output.as_file().metadata()?.len();
-	let target_size = mb_to_bytes(TARGET_SIZE_MB);
-	if output_file_size > target_size {
-		return compress_video(input, attempt + 1);
+	match media_type {
+		MediaType::Image => {
+			compress_image(input, workspace, target_size).await
+		}
+		MediaType::Video => Ok((
+			compress_video(input, info, workspace, target_size).await?,
+			"mp4".to_string(),
+		)),
+		MediaType::Audio => {
+			compress_audio(input, info, workspace, target_size).await
+		}
+		MediaType::Unknown => Err(anyhow!("Unsupported file type.")),
 	}
+}
 
-	Ok(output)
+/// The share of the target kept free for the container, which the
+/// bitrates don't include.
+const CONTAINER_OVERHEAD: f64 = 0.03;
+/// How many times the bitrate is lowered when the output still comes out
+/// too big.
+const MAX_ATTEMPTS: u32 = 3;
+/// Below this, H.264 turns into blocks, so a smaller resolution or frame
+/// rate looks better than the same bitrate spread thinner.
+const MIN_BITS_PER_PIXEL: f64 = 0.06;
+/// The shorter side of each resolution tried, largest first.
+const RESOLUTION_LADDER: &[u32] = &[1440, 1080, 720, 540, 480, 360, 240, 144];
+const MAX_FRAME_RATE: f64 = 60.0;
+/// The frame rate used once the resolution has to drop too.
+const REDUCED_FRAME_RATE: f64 = 30.0;
+const MIN_VIDEO_BITRATE: u64 = 32_000;
+const MIN_AUDIO_BITRATE: u64 = 24_000;
+const MAX_AUDIO_BITRATE: u64 = 128_000;
+
+/// The bitrates, size and frame rate for one encode.
+#[derive(Debug, Clone, PartialEq)]
+struct VideoPlan {
+	/// Bits per second.
+	video_bitrate: u64,
+	/// Bits per second, or `None` if there's no audio to keep.
+	audio_bitrate: Option<u64>,
+	/// The shorter side to scale down to, if it's smaller than the input.
+	short_side: Option<u32>,
+	/// The frame rate to drop to, if it's lower than the input's.
+	frame_rate: Option<f64>,
 }
 
-fn compress_image(input: &Path, attempt: u8) -> Result<NamedTempFile> {
-	if !input.exists() {
-		return Err(anyhow!(
-			"Input file does not exist: {}",
-			input.display()
-		));
+async fn compress_video(
+	input: &Path,
+	info: &MediaInfo,
+	workspace: &Workspace,
+	target_size: u64,
+) -> Result<PathBuf> {
+	let output = workspace.output_path("compressed.mp4");
+
+	// If the file's already small enough, it only has to play.
+	if fs::metadata(input)?.len() <= target_size {
+		debug!("File is already small enough.");
+		let playable = make_playable(input, info, workspace).await?;
+		if fs::metadata(&playable)?.len() <= target_size {
+			return Ok(playable);
+		}
 	}
-	if attempt >= 3 {
-		return Err(anyhow!("Ran out of attempts while compressing image."));
+
+	let duration = info
+		.duration
+		.ok_or_else(|| anyhow!("Couldn't read the video's duration."))?;
+	let (video_bitrate, audio_bitrate) =
+		video_budget(info, duration, target_size)?;
+	let mut plan = plan_video(info, video_bitrate, audio_bitrate);
+
+	for attempt in 1..=MAX_ATTEMPTS {
+		debug!(?plan, attempt, "Encoding the video.");
+		encode_video_two_pass(input, &output, &plan, duration, workspace)
+			.await?;
+
+		let output_size = fs::metadata(&output)?.len();
+		if output_size <= target_size {
+			return Ok(output);
+		}
+		debug!(
+			"The output was {}, over the target.",
+			format_bytes(output_size)
+		);
+		// Aim lower by however much it went over, plus a margin.
+		let ratio = target_size as f64 / output_size as f64 * 0.95;
+		let video_bitrate = (plan.video_bitrate as f64 * ratio) as u64;
+		if video_bitrate < MIN_VIDEO_BITRATE {
+			break;
+		}
+		plan = plan_video(info, video_bitrate, plan.audio_bitrate);
 	}
 
-	let input_path = input.to_str().unwrap();
-
-	let output = tempfile::NamedTempFile::new()?;
-	let output_path = output.path().to_str().unwrap();
-
-	// Ensure quality is between 0 and 100
-	let max_fps = 30;
-
-	println!("Converting image.");
-
-	let mut ffmpeg_command = Command::new("ffmpeg");
-
-	ffmpeg_command.args([
-		"-y",
-		"-i",
-		input_path,
-		"-vf",
-		&format!("fps={}", max_fps),
-		"-vcodec",
-		"libwebp",
-		"-lossless",
-		"1",
-		"-compression_level",
-		"6",
-		"-loop",
-		"0",
-		"-preset",
-		"picture",
-		"-an",
-		"-vsync",
-		"vfr",
-		"-f",
-		"webp",
-	]);
-
-	println!("Compressing image.");
-	ffmpeg_command.args(["-q:v", &(90 - (attempt as u64 * 5)).to_string()]);
-
-	ffmpeg_command.arg(output_path);
-
-	let ffmpeg_output = ffmpeg_command
-		.output()
-		.map_err(|e| anyhow!("Failed to execute FFmpeg: {}", e))?;
+	Err(anyhow!(
+		"Couldn't get the video under {}.",
+		format_bytes(target_size)
+	))
+}
 
-	if !ffmpeg_output.status.success() {
+/// Splits the target into video and audio bitrates. The audio gets an
+/// eighth of the total, within reason, and never more than it had.
+fn video_budget(
+	info: &MediaInfo,
+	duration: Duration,
+	target_size: u64,
+) -> Result<(u64, Option<u64>)> {
+	let usable_bits = target_size as f64 * 8.0 * (1.0 - CONTAINER_OVERHEAD);
+	let total_bitrate = (usable_bits / duration.as_secs_f64()) as u64;
+
+	let audio_bitrate = info.audio_stream().map(|audio| {
+		let budget =
+			(total_bitrate / 8).clamp(MIN_AUDIO_BITRATE, MAX_AUDIO_BITRATE);
+		match audio.bit_rate {
+			Some(source) => budget.min(source.max(MIN_AUDIO_BITRATE)),
+			None => budget,
+		}
+	});
+	let video_bitrate =
+		total_bitrate.saturating_sub(audio_bitrate.unwrap_or(0));
+	if video_bitrate < MIN_VIDEO_BITRATE {
 		return Err(anyhow!(
-			"Compression failed: {}",
-			String::from_utf8_lossy(&ffmpeg_output.stderr)
+			"The video is too long to fit in {}.",
+			format_bytes(target_size)
 		));
 	}
+	Ok((video_bitrate, audio_bitrate))
+}
 
-	let output_file_size = output.as_file().metadata()?.len();
-	let target_size = mb_to_bytes(TARGET_SIZE_MB);
-	if output_file_size > target_size {
-		return compress_image(input, attempt + 1);
+/// Picks the largest size and frame rate that still get
+/// [`MIN_BITS_PER_PIXEL`] from the bitrate. High frame rates are dropped to
+/// [`REDUCED_FRAME_RATE`] before the resolution, and the smallest rung is
+/// used if nothing fits.
+fn plan_video(
+	info: &MediaInfo,
+	video_bitrate: u64,
+	audio_bitrate: Option<u64>,
+) -> VideoPlan {
+	let mut plan = VideoPlan {
+		video_bitrate,
+		audio_bitrate,
+		short_side: None,
+		frame_rate: None,
+	};
+	let Some(video) = info.video_stream() else {
+		return plan;
+	};
+	let (Some(width), Some(height)) = (video.width, video.height) else {
+		return plan;
+	};
+	let (short_side, long_side) = (width.min(height), width.max(height));
+	if short_side == 0 {
+		return plan;
 	}
+	let source_frame_rate = video.frame_rate.unwrap_or(REDUCED_FRAME_RATE);
 
-	Ok(output)
+	let mut candidates =
+		vec![(short_side, source_frame_rate.min(MAX_FRAME_RATE))];
+	if source_frame_rate > REDUCED_FRAME_RATE {
+		candidates.push((short_side, REDUCED_FRAME_RATE));
+	}
+	candidates.extend(
+		RESOLUTION_LADDER
+			.iter()
+			.filter(|rung| **rung < short_side)
+			.map(|rung| (*rung, source_frame_rate.min(REDUCED_FRAME_RATE))),
+	);
+	let bits_per_pixel = |(short, frame_rate): (u32, f64)| {
+		let long = long_side as f64 * short as f64 / short_side as f64;
+		video_bitrate as f64 / (short as f64 * long * frame_rate)
+	};
+	let (short, frame_rate) = candidates
+		.iter()
+		.copied()
+		.find(|candidate| bits_per_pixel(*candidate) >= MIN_BITS_PER_PIXEL)
+		.or_else(|| candidates.last().copied())
+		.unwrap_or((short_side, source_frame_rate));
+
+	plan.short_side = (short < short_side).then_some(short);
+	plan.frame_rate = (frame_rate < source_frame_rate).then_some(frame_rate);
+	plan
 }
 
-pub fn file_to_named_temp_file(file: &mut File) -> Result<NamedTempFile> {
-	let mut tempfile = NamedTempFile::new()?;
-	let mut buffer = Vec::new();
-	file.read_to_end(&mut buffer)?;
-	tempfile.write_all(&buffer[..])?;
-	Ok(tempfile)
+/// Runs the analysis pass and then the real one, which spends the bitrate
+/// where the first pass found it was needed.
+async fn encode_video_two_pass(
+	input: &Path,
+	output: &Path,
+	plan: &VideoPlan,
+	duration: Duration,
+	workspace: &Workspace,
+) -> Result<()> {
+	let video_filter = video_filter(plan);
+	for pass in 1..=2 {
+		let mut ffmpeg_command = ffmpeg_command();
+		// The pass log is written next to the job's files.
+		ffmpeg_command
+			.current_dir(workspace.path())
+			.args(["-y", "-nostdin"])
+			.args(ffmpeg_input(input))
+			.args(["-map", "0:v:0", "-vf", &video_filter])
+			.args(PLAYABLE_H264_ARGS)
+			.args(["-b:v", &plan.video_bitrate.to_string()])
+			.args(["-pass", &pass.to_string(), "-passlogfile", "x264"]);
+		if pass == 1 {
+			ffmpeg_command.args(["-an", "-f", "null", "-"]);
+		} else {
+			match plan.audio_bitrate {
+				Some(audio_bitrate) => ffmpeg_command
+					.args(["-map", "0:a:0", "-c:a", "aac"])
+					.args(["-b:a", &audio_bitrate.to_string()]),
+				None => ffmpeg_command.arg("-an"),
+			};
+			ffmpeg_command.args(PLAYABLE_MP4_ARGS).arg(output);
+		}
+
+		let ffmpeg_output =
+			run_ffmpeg_pass(ffmpeg_command, Some(duration), pass - 1, 2)
+				.await?;
+		if !ffmpeg_output.status.success() {
+			return Err(anyhow!(
+				"Compression failed: {}",
+				String::from_utf8_lossy(&ffmpeg_output.stderr)
+			));
+		}
+	}
+	Ok(())
 }
 
-pub fn estimate_media_size(
-	video_bitrate: u64,
-	audio_bitrate: u64,
-	duration_seconds: f64,
-) -> f64 {
-	// Convert bitrate
-	let video_bitrate_mbps = video_bitrate as f64 / 1024.0 / 1024.0;
-	let audio_bitrate_mbps = audio_bitrate as f64 / 1024.0 / 1024.0;
-
-	// Calculate file size in bytes
-	(video_bitrate_mbps * duration_seconds) / 8.0
-		+ (audio_bitrate_mbps * duration_seconds) / 8.0
+/// Scales the shorter side down and lowers the frame rate if the plan says
+/// to. Either way the dimensions are made even, which 4:2:0 needs.
+fn video_filter(plan: &VideoPlan) -> String {
+	let mut filters = vec![match plan.short_side {
+		// The sides are compared after ffmpeg applies the rotation, and
+		// `-2` keeps the aspect ratio with an even length.
+		Some(short_side) => format!(
+			"scale=w='if(gte(iw,ih),-2,{0})':h='if(gte(iw,ih),{0},-2)'",
+			short_side
+		),
+		None => "scale=trunc(iw/2)*2:trunc(ih/2)*2".to_string(),
+	}];
+	if let Some(frame_rate) = plan.frame_rate {
+		filters.push(format!("fps={}", frame_rate));
+	}
+	filters.join(",")
 }
 
-pub fn estimate_video_bitrate(
-	starting_bitrate: u64,
-	audio_bitrate: u64,
-	duration_seconds: f64,
+/// Opus sounds fine far lower than AAC, but only AAC in an M4A can keep
+/// cover art, so AAC is only used above this.
+const MIN_AAC_BITRATE: u64 = 64_000;
+const MIN_OPUS_BITRATE: u64 = 12_000;
+const MAX_OPUS_BITRATE: u64 = 256_000;
+const MAX_AAC_BITRATE: u64 = 320_000;
+/// The cover art is shrunk to this width so its size is predictable.
+const COVER_ART_WIDTH: u32 = 600;
+/// What the shrunk cover art is assumed to take out of the target.
+const COVER_ART_BUDGET: u64 = 200 * 1024;
+
+/// Writes Opus in an Ogg, or AAC in an M4A when there's cover art to keep
+/// and enough room for it, and returns its path and extension. Tags are
+/// copied either way.
+async fn compress_audio(
+	input: &Path,
+	info: &MediaInfo,
+	workspace: &Workspace,
 	target_size: u64,
-) -> u64 {
-	let mut new_bitrate = starting_bitrate as f64;
-	while (new_bitrate * duration_seconds)
-		+ (audio_bitrate as f64 * duration_seconds)
-		> target_size as f64
-	{
-		new_bitrate -= 1024.0;
+) -> Result<(PathBuf, String)> {
+	if fs::metadata(input)?.len() <= target_size {
+		debug!("File is already small enough.");
+		let extension = input
+			.extension()
+			.map(|extension| extension.to_string_lossy().to_string())
+			.unwrap_or_else(|| "mp3".to_string());
+		let output =
+			workspace.output_path(&format!("compressed.{}", extension));
+		fs::copy(input, &output)?;
+		return Ok((output, extension));
 	}
-	new_bitrate as u64
+
+	let duration = info
+		.duration
+		.ok_or_else(|| anyhow!("Couldn't read the audio's duration."))?;
+	let audio = info
+		.audio_stream()
+		.ok_or_else(|| anyhow!("There's no audio to compress."))?;
+	let usable_bits = |reserved: u64| {
+		target_size.saturating_sub(reserved) as f64
+			* 8.0 * (1.0 - CONTAINER_OVERHEAD)
+	};
+	let bitrate_for = |reserved: u64| {
+		let bitrate = (usable_bits(reserved) / duration.as_secs_f64()) as u64;
+		// More than the source has only makes the file bigger.
+		match audio.bit_rate {
+			Some(source) => bitrate.min(source),
+			None => bitrate,
+		}
+	};
+
+	let cover_art = info.streams.iter().find(|stream| stream.attached_pic);
+	let keep_cover_art = cover_art.is_some()
+		&& bitrate_for(COVER_ART_BUDGET) >= MIN_AAC_BITRATE;
+	let (extension, mut bitrate) = if keep_cover_art {
+		("m4a", bitrate_for(COVER_ART_BUDGET).min(MAX_AAC_BITRATE))
+	} else {
+		("ogg", bitrate_for(0).min(MAX_OPUS_BITRATE))
+	};
+	let output = workspace.output_path(&format!("compressed.{}", extension));
+
+	for attempt in 1..=MAX_ATTEMPTS {
+		if bitrate < MIN_OPUS_BITRATE {
+			return Err(anyhow!(
+				"The audio is too long to fit in {}.",
+				format_bytes(target_size)
+			));
+		}
+		debug!(bitrate, attempt, extension, "Encoding the audio.");
+
+		let mut ffmpeg_command = ffmpeg_command();
+		ffmpeg_command
+			.args(["-y", "-nostdin"])
+			.args(ffmpeg_input(input))
+			.args(["-map", &format!("0:{}", audio.index)])
+			.args(["-map_metadata", "0"]);
+		match cover_art.filter(|_| keep_cover_art) {
+			Some(cover_art) => ffmpeg_command
+				.args(["-map", &format!("0:{}", cover_art.index)])
+				.args(["-c:a", "aac", "-c:v", "mjpeg"])
+				.args([
+					"-vf",
+					&format!("scale='min(iw,{})':-2", COVER_ART_WIDTH),
+				])
+				.args(["-disposition:v:0", "attached_pic", "-f", "ipod"]),
+			None => {
+				ffmpeg_command.args(["-vn", "-c:a", "libopus", "-f", "ogg"])
+			}
+		};
+		// libopus rejects some surround layouts, and the bits are better
+		// spent on two channels anyway.
+		if audio.channels.is_some_and(|channels| channels > 2) {
+			ffmpeg_command.args(["-ac", "2"]);
+		}
+		ffmpeg_command
+			.args(["-b:a", &bitrate.to_string()])
+			.arg(&output);
+
+		let ffmpeg_output =
+			run_ffmpeg_with_progress(ffmpeg_command, Some(duration)).await?;
+		if !ffmpeg_output.status.success() {
+			return Err(anyhow!(
+				"Compression failed: {}",
+				String::from_utf8_lossy(&ffmpeg_output.stderr)
+			));
+		}
+
+		let output_size = fs::metadata(&output)?.len();
+		if output_size <= target_size {
+			return Ok((output, extension.to_string()));
+		}
+		debug!(
+			"The output was {}, over the target.",
+			format_bytes(output_size)
+		);
+		let ratio = target_size as f64 / output_size as f64 * 0.95;
+		bitrate = (bitrate as f64 * ratio) as u64;
+	}
+
+	Err(anyhow!(
+		"Couldn't get the audio under {}.",
+		format_bytes(target_size)
+	))
 }
 
-#[derive(Debug)]
-pub struct VideoInfo {
-	pub video_bitrate: u64,
-	pub audio_bitrate: u64,
-	pub duration: f64,
+/// Frames per second that animated images are capped at.
+const IMAGE_FRAME_RATE: u32 = 30;
+/// How many encodes an image gets, lossless one included.
+const MAX_IMAGE_ATTEMPTS: u32 = 8;
+/// The WebP qualities tried after lossless, highest first. Only the last
+/// one is scaled down.
+const IMAGE_QUALITIES: &[u32] = &[90, 75, 60, 40];
+/// Below this, the image is too small to be worth sending.
+const MIN_IMAGE_SCALE: f64 = 0.1;
+
+/// The quality and size for one encode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ImagePlan {
+	/// The WebP quality, or `None` for lossless.
+	quality: Option<u32>,
+	/// How much the sides are scaled by, up to 1.
+	scale: f64,
 }
 
-pub fn get_video_info(file_path: &Path) -> Result<VideoInfo> {
-	// Retrieve bitrate and resolution
-	let duration = get_media_stream_info(file_path, "v:0", "duration")?
-		.trim()
-		.parse::<f64>()
-		.unwrap();
-	let video_bitrate = get_media_stream_info(file_path, "v:0", "bit_rate")?
-		.trim()
-		.parse::<u64>()
-		.unwrap();
-	let audio_bitrate = get_media_stream_info(file_path, "a:0", "bit_rate")?
-		.trim()
-		.parse::<u64>()
-		.unwrap();
-
-	Ok(VideoInfo {
-		video_bitrate,
-		audio_bitrate,
-		duration,
-	})
+/// Writes a WebP and returns its path and extension, unless the image
+/// already fits, in which case it's copied as it is.
+async fn compress_image(
+	input: &Path,
+	workspace: &Workspace,
+	target_size: u64,
+) -> Result<(PathBuf, String)> {
+	if fs::metadata(input)?.len() <= target_size {
+		debug!("File is already small enough.");
+		let extension = input
+			.extension()
+			.map(|extension| extension.to_string_lossy().to_string())
+			.unwrap_or_else(|| "png".to_string());
+		let output =
+			workspace.output_path(&format!("compressed.{}", extension));
+		fs::copy(input, &output)?;
+		return Ok((output, extension));
+	}
+
+	let output = workspace.output_path("compressed.webp");
+	let mut plan = ImagePlan {
+		quality: None,
+		scale: 1.0,
+	};
+
+	for attempt in 1..=MAX_IMAGE_ATTEMPTS {
+		debug!(?plan, attempt, "Encoding the image.");
+		encode_image(input, &output, &plan).await?;
+
+		let output_size = fs::metadata(&output)?.len();
+		if output_size <= target_size {
+			return Ok((output, "webp".to_string()));
+		}
+		debug!(
+			"The output was {}, over the target.",
+			format_bytes(output_size)
+		);
+		match next_image_plan(&plan, target_size, output_size) {
+			Some(next) => plan = next,
+			None => break,
+		}
+	}
+
+	Err(anyhow!(
+		"Couldn't get the image under {}.",
+		format_bytes(target_size)
+	))
 }
 
-pub fn get_media_stream_info(
-	file_path: &Path,
-	stream: &str,
-	entry: &str,
-) -> Result<String> {
-	// Execute the ffprobe command
-	let output = Command::new("ffprobe")
-		.arg("-v")
-		.arg("error")
-		.arg("-select_streams")
-		.arg(stream)
-		.arg("-show_entries")
-		.arg(format!("stream={}", entry))
-		.arg("-of")
-		.arg("default=noprint_wrappers=1:nokey=1")
-		.arg(file_path.to_str().unwrap())
-		.output()
-		.map_err(|e| anyhow!("Failed to execute ffprobe: {}", e))?;
-
-	// Check if the command was successful
-	if !output.status.success() {
-		return Err(anyhow!(
-			"ffprobe error: {}",
-			str::from_utf8(&output.stderr).unwrap_or("unknown error")
-		));
+/// Tries the next lower quality, and once those run out, scales the image
+/// down instead. `None` means it would be too small.
+fn next_image_plan(
+	plan: &ImagePlan,
+	target_size: u64,
+	output_size: u64,
+) -> Option<ImagePlan> {
+	let lower_quality = match plan.quality {
+		None => IMAGE_QUALITIES.first(),
+		Some(quality) => {
+			IMAGE_QUALITIES.iter().find(|lower| **lower < quality)
+		}
+	};
+	if let Some(quality) = lower_quality {
+		return Some(ImagePlan {
+			quality: Some(*quality),
+			..*plan
+		});
 	}
+	// The size goes with the area, so the sides shrink by the square root
+	// of however much it went over, plus a margin.
+	let ratio = target_size as f64 / output_size as f64;
+	let scale = plan.scale * ratio.sqrt() * 0.95;
+	(scale >= MIN_IMAGE_SCALE).then_some(ImagePlan { scale, ..*plan })
+}
 
-	// Parse the output
-	let output_str = str::from_utf8(&output.stdout)
-		.map_err(|e| anyhow!("Failed to parse output: {}", e))?;
+async fn encode_image(
+	input: &Path,
+	output: &Path,
+	plan: &ImagePlan,
+) -> Result<()> {
+	let mut filters = vec![format!("fps={}", IMAGE_FRAME_RATE)];
+	if plan.scale < 1.0 {
+		filters.push(format!(
+			"scale=w='max(1,iw*{0})':h='max(1,ih*{0})'",
+			plan.scale
+		));
+	}
 
-	Ok(output_str.to_string())
+	let mut ffmpeg_command = ffmpeg_command();
+	ffmpeg_command
+		.arg("-y")
+		.args(ffmpeg_input(input))
+		.args(["-vf", &filters.join(",")])
+		.args(["-c:v", "libwebp", "-compression_level", "6"])
+		.args(["-loop", "0", "-preset", "picture"]);
+	match plan.quality {
+		Some(quality) => ffmpeg_command.args([
+			"-lossless",
+			"0",
+			"-quality",
+			&quality.to_string(),
+		]),
+		None => ffmpeg_command.args(["-lossless", "1", "-quality", "100"]),
+	};
+	ffmpeg_command
+		.args(["-an", "-vsync", "vfr", "-f", "webp"])
+		.arg(output);
+
+	let ffmpeg_output =
+		run_ffmpeg_with_progress(ffmpeg_command, None).await?;
+	if !ffmpeg_output.status.success() {
+		return Err(anyhow!(
+			"Compression failed: {}",
+			String::from_utf8_lossy(&ffmpeg_output.stderr)
+		));
+	}
+	Ok(())
 }
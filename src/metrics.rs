@@ -0,0 +1,170 @@
+use std::{
+	net::SocketAddr,
+	sync::LazyLock,
+	time::{Duration, Instant},
+};
+
+use anyhow::{Context as _, Result};
+use prometheus::{
+	exponential_buckets, register_histogram_vec, register_int_counter_vec,
+	Encoder, HistogramVec, IntCounterVec, TextEncoder,
+};
+use tokio::{
+	io::{AsyncReadExt, AsyncWriteExt},
+	net::{TcpListener, TcpStream},
+};
+use tracing::{info, warn};
+
+/// Media commands can take minutes, so the buckets go from 50ms to about
+/// 7 minutes.
+fn duration_buckets() -> Vec<f64> {
+	exponential_buckets(0.05, 2.0, 14).unwrap()
+}
+
+static COMMAND_INVOCATIONS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+	register_int_counter_vec!(
+		"selfbot_command_invocations_total",
+		"Command invocations.",
+		&["command"]
+	)
+	.unwrap()
+});
+
+static COMMAND_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+	register_histogram_vec!(
+		"selfbot_command_duration_seconds",
+		"How long commands took from the interaction to the last reply.",
+		&["command"],
+		duration_buckets()
+	)
+	.unwrap()
+});
+
+static COMMAND_FAILURES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+	register_int_counter_vec!(
+		"selfbot_command_failures_total",
+		"Commands that returned an error or panicked.",
+		&["command", "kind"]
+	)
+	.unwrap()
+});
+
+static PROCESS_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+	register_histogram_vec!(
+		"selfbot_process_duration_seconds",
+		"How long OS processes such as ffmpeg ran.",
+		&["tool"],
+		duration_buckets()
+	)
+	.unwrap()
+});
+
+static PROCESS_EXITS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+	register_int_counter_vec!(
+		"selfbot_process_exits_total",
+		"OS process exits by exit code.",
+		&["tool", "code"]
+	)
+	.unwrap()
+});
+
+static HTTP_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+	register_histogram_vec!(
+		"selfbot_http_request_duration_seconds",
+		"Latency of outbound HTTP requests.",
+		&["host", "status"]
+	)
+	.unwrap()
+});
+
+/// The label for a command, which is the name Discord shows.
+pub fn command_label(command: &crate::commands::Command) -> String {
+	match &command.context_menu_name {
+		Some(name) => name.clone(),
+		None => command
+			.qualified_name
+			.split(' ')
+			.next()
+			.unwrap_or_default()
+			.to_string(),
+	}
+}
+
+pub fn observe_command(command: &str, duration: Duration) {
+	COMMAND_INVOCATIONS.with_label_values(&[command]).inc();
+	COMMAND_DURATION
+		.with_label_values(&[command])
+		.observe(duration.as_secs_f64());
+}
+
+/// `kind` is `error`, `panic` or `cancelled`.
+pub fn observe_command_failure(command: &str, kind: &str) {
+	COMMAND_FAILURES.with_label_values(&[command, kind]).inc();
+}
+
+/// `code` is `None` when the process was killed by a signal.
+pub fn observe_process(tool: &str, duration: Duration, code: Option<i32>) {
+	let code = code.map_or("signal".to_string(), |code| code.to_string());
+	PROCESS_DURATION
+		.with_label_values(&[tool])
+		.observe(duration.as_secs_f64());
+	PROCESS_EXITS.with_label_values(&[tool, &code]).inc();
+}
+
+/// `status` is `None` when the request failed before a response came back.
+pub fn observe_http(host: &str, start: Instant, status: Option<u16>) {
+	let status =
+		status.map_or("error".to_string(), |status| status.to_string());
+	HTTP_DURATION
+		.with_label_values(&[host, &status])
+		.observe(start.elapsed().as_secs_f64());
+}
+
+/// Binds the metrics listener and serves the text exposition format on
+/// every path. Failing to bind is an error so that a typo in the address
+/// doesn't go unnoticed.
+pub async fn serve(address: SocketAddr) -> Result<()> {
+	let listener = TcpListener::bind(address).await.with_context(|| {
+		format!("Failed to bind the metrics listener to {}", address)
+	})?;
+	info!("Serving metrics on http://{}/metrics", address);
+
+	tokio::spawn(async move {
+		loop {
+			match listener.accept().await {
+				Ok((stream, _)) => {
+					tokio::spawn(async move {
+						if let Err(e) = respond(stream).await {
+							warn!("Failed to serve metrics: {}", e);
+						}
+					});
+				}
+				Err(e) => {
+					warn!("Failed to accept a metrics connection: {}", e)
+				}
+			}
+		}
+	});
+	Ok(())
+}
+
+async fn respond(mut stream: TcpStream) -> Result<()> {
+	// Scrapers send a small GET request. Its contents don't matter.
+	let mut request = [0; 1024];
+	let _ = stream.read(&mut request).await?;
+
+	let encoder = TextEncoder::new();
+	let mut body = Vec::new();
+	encoder.encode(&prometheus::gather(), &mut body)?;
+
+	let header = format!(
+		"HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: \
+		 {}\r\nConnection: close\r\n\r\n",
+		encoder.format_type(),
+		body.len()
+	);
+	stream.write_all(header.as_bytes()).await?;
+	stream.write_all(&body).await?;
+	stream.shutdown().await?;
+	Ok(())
+}
@@ -1,10 +1,74 @@
 use std::{
-	io::{BufRead, BufReader},
-	process::{self, Output},
-	thread,
+	collections::HashMap,
+	process::{self, Output, Stdio},
+	sync::{LazyLock, RwLock},
+	time::{Duration, Instant},
 };
 
-use anyhow::Result;
+use anyhow::{anyhow, Context as _, Result};
+use tokio::{
+	io::{AsyncBufReadExt, AsyncRead, BufReader},
+	sync::watch,
+};
+use tracing::info;
+
+use crate::{config::Config, logging, metrics};
+
+/// Only the start of stdout is kept since that's where tools like ffprobe
+/// put what we parse.
+const MAX_CAPTURED_STDOUT: usize = 16 * 1024 * 1024;
+/// Only the end of stderr is kept since that's where the error usually is.
+const MAX_CAPTURED_STDERR: usize = 64 * 1024;
+
+/// The configured timeouts, updated whenever the config reloads.
+static TIMEOUTS: LazyLock<RwLock<Timeouts>> =
+	LazyLock::new(|| RwLock::new(Timeouts::default()));
+
+/// Set once the bot starts shutting down so running processes are killed.
+static SHUTDOWN: LazyLock<watch::Sender<bool>> =
+	LazyLock::new(|| watch::channel(false).0);
+
+#[derive(Debug, Default)]
+struct Timeouts {
+	default: Duration,
+	per_tool: HashMap<String, Duration>,
+}
+
+/// Remembers the process timeouts from the config. Called again whenever
+/// the config reloads.
+pub fn set_timeouts(config: &Config) {
+	*TIMEOUTS.write().unwrap_or_else(|e| e.into_inner()) = Timeouts {
+		default: Duration::from_secs(config.process_timeout),
+		per_tool: config
+			.process_timeouts
+			.iter()
+			.map(|(tool, secs)| (tool.clone(), Duration::from_secs(*secs)))
+			.collect(),
+	};
+}
+
+fn timeout_for(tool: &str) -> Duration {
+	let timeouts = TIMEOUTS.read().unwrap_or_else(|e| e.into_inner());
+	timeouts
+		.per_tool
+		.get(tool)
+		.copied()
+		.unwrap_or(timeouts.default)
+}
+
+/// Kills every running process and refuses to start new ones.
+pub fn shutdown() {
+	SHUTDOWN.send_replace(true);
+}
+
+async fn wait_for_shutdown() {
+	let mut shutdown = SHUTDOWN.subscribe();
+	while !*shutdown.borrow_and_update() {
+		if shutdown.changed().await.is_err() {
+			std::future::pending::<()>().await;
+		}
+	}
+}
 
 pub fn command_to_string(cmd: &process::Command) -> String {
 	// Get the program name
@@ -25,43 +89,132 @@ pub fn command_to_string(cmd: &process::Command) -> String {
 	}
 }
 
-// A function that runs a command on the OS.
-// It automatically prints the output and returns the exit code.
-pub fn run_os_command(
+/// Runs a command on the OS once, logging its output as it comes in.
+///
+/// The process is killed if it runs past its timeout, if the bot shuts
+/// down, or if the returned future is dropped because the command that
+/// started it was cancelled.
+pub async fn run_os_command(
+	tag: impl ToString,
+	command: process::Command,
+) -> Result<Output> {
+	run_os_command_with_stdout(tag, command, |_| {}).await
+}
+
+/// Like [`run_os_command`], but hands each line of stdout to `on_line` as
+/// it comes in instead of logging it. For tools that report their progress
+/// on stdout, such as `ffmpeg -progress pipe:1`.
+pub async fn run_os_command_with_stdout(
 	tag: impl ToString,
-	mut command: process::Command,
+	command: process::Command,
+	mut on_line: impl FnMut(&str) + Send,
 ) -> Result<Output> {
 	let tag = tag.to_string();
-	println!("[{}] {}", tag, command_to_string(&command));
+	info!(
+		tool = %tag,
+		"Running {}",
+		logging::redact(&command_to_string(&command))
+	);
+	if *SHUTDOWN.borrow() {
+		return Err(anyhow!("The bot is shutting down."));
+	}
 
-	command.stdout(process::Stdio::piped());
-	command.stderr(process::Stdio::piped());
+	let mut command = tokio::process::Command::from(command);
+	command
+		.stdin(Stdio::null())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.kill_on_drop(true);
 
-	let mut child = command.spawn()?;
+	let timeout = timeout_for(&tag);
+	let start = Instant::now();
+	let mut child = command
+		.spawn()
+		.with_context(|| format!("Failed to start {}", tag))?;
 
 	let stdout = child.stdout.take().expect("Failed to capture stdout");
 	let stderr = child.stderr.take().expect("Failed to capture stderr");
 
-	let stdout_tag = format!("[{}]", tag);
-	let stderr_tag = format!("[{}]", tag);
-	let stdout_thread = thread::spawn(move || {
-		let stdout_reader = BufReader::new(stdout);
-		for line in stdout_reader.lines().map_while(Result::ok) {
-			println!("{} {}", stdout_tag, line);
-		}
-	});
+	let finished = async {
+		let (stdout, stderr) = tokio::join!(
+			capture(
+				stdout,
+				&tag,
+				"stdout",
+				MAX_CAPTURED_STDOUT,
+				false,
+				Some(&mut on_line),
+			),
+			capture(stderr, &tag, "stderr", MAX_CAPTURED_STDERR, true, None,),
+		);
+		child.wait().await.map(|status| Output {
+			status,
+			stdout,
+			stderr,
+		})
+	};
 
-	let stderr_thread = thread::spawn(move || {
-		let stderr_reader = BufReader::new(stderr);
-		for line in stderr_reader.lines().map_while(Result::ok) {
-			eprintln!("{} {}", stderr_tag, line);
+	let stopped_because = tokio::select! {
+		output = finished => {
+			let output = output?;
+			metrics::observe_process(
+				&tag,
+				start.elapsed(),
+				output.status.code(),
+			);
+			return Ok(output);
 		}
-	});
+		_ = tokio::time::sleep(timeout) => {
+			format!("timed out after {}s", timeout.as_secs())
+		}
+		_ = wait_for_shutdown() => "was stopped because the bot is shutting down"
+			.to_string(),
+	};
 
-	// Wait for the command to complete and for both threads to finish
-	child.wait()?;
-	stdout_thread.join().expect("Stdout thread panicked");
-	stderr_thread.join().expect("Stderr thread panicked");
+	child.kill().await.ok();
+	metrics::observe_process(&tag, start.elapsed(), None);
+	Err(anyhow!("{} {}.", tag, stopped_because))
+}
 
-	Ok(command.output()?)
+/// Logs each line of `stream`, or passes it to `on_line` if there is one,
+/// and keeps up to `limit` bytes of it, either the start or the end.
+async fn capture(
+	stream: impl AsyncRead + Unpin,
+	tag: &str,
+	name: &str,
+	limit: usize,
+	keep_end: bool,
+	mut on_line: Option<&mut (dyn FnMut(&str) + Send)>,
+) -> Vec<u8> {
+	let mut reader = BufReader::new(stream);
+	let mut captured = Vec::new();
+	let mut line = Vec::new();
+	loop {
+		line.clear();
+		match reader.read_until(b'\n', &mut line).await {
+			Ok(0) | Err(_) => break,
+			Ok(_) => {}
+		}
+		let text = String::from_utf8_lossy(&line);
+		match on_line.as_mut() {
+			Some(on_line) => on_line(text.trim_end()),
+			None => info!(
+				tool = %tag,
+				stream = name,
+				"{}",
+				logging::redact(text.trim_end())
+			),
+		}
+
+		if keep_end {
+			captured.extend_from_slice(&line);
+			if captured.len() > limit {
+				captured.drain(..captured.len() - limit);
+			}
+		} else if captured.len() < limit {
+			let remaining = limit - captured.len();
+			captured.extend_from_slice(&line[..line.len().min(remaining)]);
+		}
+	}
+	captured
 }
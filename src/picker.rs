@@ -0,0 +1,172 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use poise::{
+	serenity_prelude::{
+		ComponentInteractionCollector, ComponentInteractionDataKind,
+		CreateActionRow, CreateAllowedMentions, CreateInteractionResponse,
+		CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption,
+		Message, User,
+	},
+	CreateReply,
+};
+use tracing::warn;
+
+use crate::{
+	config::ApplicationContext,
+	helpers::{format_bytes, AttachmentOrThumbnail},
+};
+
+/// How long the picker waits for a choice.
+const PICKER_TIMEOUT: Duration = Duration::from_secs(120);
+/// Discord's limit for the options in a select menu.
+const MAX_OPTIONS: usize = 25;
+/// Discord's limit for an option's label and description.
+const MAX_OPTION_LENGTH: usize = 100;
+
+/// Asks which of the message's attachments, embed media, stickers and
+/// custom emoji to use with a select menu only the user can see. A message
+/// with just one of them skips the question.
+pub async fn pick_attachment(
+	ctx: ApplicationContext<'_>,
+	message: &Message,
+) -> Result<AttachmentOrThumbnail> {
+	let candidates = AttachmentOrThumbnail::from_message(message);
+	if candidates.is_empty() {
+		return Err(anyhow!(
+			"That message has no attachments, embeds, stickers or emoji to \
+			 use."
+		));
+	}
+	pick(ctx, candidates).await
+}
+
+/// Asks whether to use the user's avatar or banner. A user without a banner
+/// skips the question.
+pub async fn pick_user_attachment(
+	ctx: ApplicationContext<'_>,
+	user: &User,
+) -> Result<AttachmentOrThumbnail> {
+	let candidates =
+		AttachmentOrThumbnail::from_user(ctx.http(), user).await?;
+	pick(ctx, candidates).await
+}
+
+/// What a context menu was used on.
+#[derive(Debug, Clone, Copy)]
+pub enum MenuTarget<'a> {
+	Message(&'a Message),
+	User(&'a User),
+}
+impl MenuTarget<'_> {
+	/// Asks which of the message's media or the user's images to use.
+	pub async fn pick(
+		self,
+		ctx: ApplicationContext<'_>,
+	) -> Result<AttachmentOrThumbnail> {
+		match self {
+			Self::Message(message) => pick_attachment(ctx, message).await,
+			Self::User(user) => pick_user_attachment(ctx, user).await,
+		}
+	}
+}
+
+async fn pick(
+	ctx: ApplicationContext<'_>,
+	mut candidates: Vec<AttachmentOrThumbnail>,
+) -> Result<AttachmentOrThumbnail> {
+	match candidates.len() {
+		0 => return Err(anyhow!("There's nothing to use.")),
+		1 => return Ok(candidates.remove(0)),
+		_ => {}
+	}
+
+	let custom_id = format!("pick_attachment:{}", ctx.interaction.id);
+	let options = candidates
+		.iter()
+		.enumerate()
+		.take(MAX_OPTIONS)
+		.map(|(index, candidate)| {
+			CreateSelectMenuOption::new(
+				truncate(&candidate.filename()),
+				index.to_string(),
+			)
+			.description(truncate(&describe(candidate)))
+		})
+		.collect();
+	let picker = ctx
+		.send(
+			CreateReply::default()
+				.allowed_mentions(CreateAllowedMentions::default())
+				.ephemeral(true)
+				.content("Which one?")
+				.components(vec![CreateActionRow::SelectMenu(
+					CreateSelectMenu::new(
+						&custom_id,
+						CreateSelectMenuKind::String { options },
+					)
+					.placeholder("Choose a file"),
+				)]),
+		)
+		.await?;
+
+	let interaction =
+		ComponentInteractionCollector::new(&ctx.serenity_context().shard)
+			.author_id(ctx.author().id)
+			.custom_ids(vec![custom_id])
+			.timeout(PICKER_TIMEOUT)
+			.await;
+
+	if let Some(interaction) = &interaction {
+		interaction
+			.create_response(ctx, CreateInteractionResponse::Acknowledge)
+			.await?;
+	}
+	if let Err(e) = picker.delete(ctx.into()).await {
+		warn!("Failed to delete an attachment picker: {}", e);
+	}
+
+	let interaction =
+		interaction.ok_or_else(|| anyhow!("Nothing was chosen in time."))?;
+	let index = match &interaction.data.kind {
+		ComponentInteractionDataKind::StringSelect { values } => {
+			values.first().and_then(|value| value.parse::<usize>().ok())
+		}
+		_ => None,
+	}
+	.ok_or_else(|| anyhow!("Nothing was chosen."))?;
+	if index >= candidates.len() {
+		return Err(anyhow!("That choice isn't there anymore."));
+	}
+	Ok(candidates.swap_remove(index))
+}
+
+/// Such as `Attachment, image/png, 1.20 MiB, 1920×1080`.
+fn describe(candidate: &AttachmentOrThumbnail) -> String {
+	let mut parts = vec![capitalize(candidate.kind()), candidate.mime_type()];
+	if let Some(size) = candidate.size() {
+		parts.push(format_bytes(size));
+	}
+	if let Some((width, height)) = candidate.dimensions() {
+		parts.push(format!("{}×{}", width, height));
+	}
+	parts.join(", ")
+}
+
+fn capitalize(text: &str) -> String {
+	let mut chars = text.chars();
+	match chars.next() {
+		Some(first) => first.to_uppercase().chain(chars).collect(),
+		None => String::new(),
+	}
+}
+
+fn truncate(text: &str) -> String {
+	if text.chars().count() <= MAX_OPTION_LENGTH {
+		return text.to_string();
+	}
+	let mut truncated =
+		text.chars().take(MAX_OPTION_LENGTH - 1).collect::<String>();
+	truncated.push('…');
+	truncated
+}
@@ -0,0 +1,298 @@
+use std::{
+	collections::HashMap, ffi::OsString, path::Path, process, time::Duration,
+};
+
+use anyhow::{anyhow, Context as _, Result};
+use serde::Deserialize;
+
+use crate::{os_command::run_os_command_with_stdout, sandbox};
+
+/// Codecs that are only used for images, animated or not.
+const IMAGE_CODECS: &[&str] = &[
+	"apng", "bmp", "dds", "exr", "gif", "hdr", "jpeg2000", "jpegls",
+	"jpegxl", "pam", "pbm", "pcx", "pgm", "png", "ppm", "psd", "qoi", "sgi",
+	"targa", "tiff", "webp", "xbm", "xwd",
+];
+
+/// What ffprobe found in a file.
+#[derive(Debug, Clone)]
+pub struct MediaInfo {
+	/// ffprobe's short names for the container, such as
+	/// `mov,mp4,m4a,3gp,3g2,mj2` or `png_pipe`.
+	pub container: String,
+	pub container_long_name: Option<String>,
+	pub duration: Option<Duration>,
+	/// The whole file's bitrate in bits per second.
+	pub bit_rate: Option<u64>,
+	pub size: Option<u64>,
+	/// The container's metadata, such as `title` or `artist`.
+	pub tags: HashMap<String, String>,
+	pub streams: Vec<StreamInfo>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StreamInfo {
+	pub index: u32,
+	pub kind: StreamKind,
+	pub codec: Option<String>,
+	pub codec_long_name: Option<String>,
+	/// Such as `High` or `High 10` for H.264.
+	pub profile: Option<String>,
+	pub width: Option<u32>,
+	pub height: Option<u32>,
+	/// Degrees as ffprobe reports them, from the display matrix or the
+	/// older `rotate` tag.
+	pub rotation: Option<i32>,
+	pub pixel_format: Option<String>,
+	/// Bits per second.
+	pub bit_rate: Option<u64>,
+	pub sample_rate: Option<u32>,
+	pub channels: Option<u32>,
+	/// Such as `stereo` or `5.1(side)`.
+	pub channel_layout: Option<String>,
+	pub frame_rate: Option<f64>,
+	pub duration: Option<Duration>,
+	pub frames: Option<u64>,
+	/// Cover art, which ffprobe lists as a video stream.
+	pub attached_pic: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamKind {
+	Video,
+	Audio,
+	Subtitle,
+	Data,
+	Attachment,
+	Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaType {
+	Image,
+	Video,
+	Audio,
+	Unknown,
+}
+
+impl MediaInfo {
+	/// The first video stream that isn't cover art.
+	pub fn video_stream(&self) -> Option<&StreamInfo> {
+		self.streams.iter().find(|stream| {
+			stream.kind == StreamKind::Video && !stream.attached_pic
+		})
+	}
+
+	pub fn audio_stream(&self) -> Option<&StreamInfo> {
+		self.streams
+			.iter()
+			.find(|stream| stream.kind == StreamKind::Audio)
+	}
+
+	/// Classifies the file by its streams. Anything with picture is an image
+	/// if it uses an image codec or has a single frame, otherwise a video.
+	/// Audio with cover art is still audio.
+	pub fn media_type(&self) -> MediaType {
+		if let Some(video) = self.video_stream() {
+			return if video.is_image() {
+				MediaType::Image
+			} else {
+				MediaType::Video
+			};
+		}
+		if self.audio_stream().is_some() {
+			return MediaType::Audio;
+		}
+		MediaType::Unknown
+	}
+}
+
+impl StreamInfo {
+	fn is_image(&self) -> bool {
+		let image_codec = self
+			.codec
+			.as_deref()
+			.is_some_and(|codec| IMAGE_CODECS.contains(&codec));
+		// JPEGs are MJPEG with one frame, and AVIFs are AV1 with one frame.
+		let single_frame = match self.frames {
+			Some(frames) => frames <= 1,
+			None => self.codec.as_deref() == Some("mjpeg"),
+		};
+		image_codec || single_frame
+	}
+}
+
+/// Runs ffprobe once and reads everything it knows about the file.
+pub async fn probe(path: &Path) -> Result<MediaInfo> {
+	parse(&probe_json(path).await?)
+}
+
+/// ffprobe's JSON for the file, for when the raw output is wanted too.
+/// [`parse`] reads it.
+pub async fn probe_json(path: &Path) -> Result<Vec<u8>> {
+	// `file:` keeps the path from being read as a protocol.
+	let mut file = OsString::from("file:");
+	file.push(path);
+	let mut ffprobe_command = process::Command::new("ffprobe");
+	ffprobe_command
+		.args([
+			"-v",
+			"error",
+			"-print_format",
+			"json",
+			"-show_format",
+			"-show_streams",
+			"-protocol_whitelist",
+			&sandbox::ALLOWED_PROTOCOLS.join(","),
+		])
+		.arg(file);
+	sandbox::limit_resources(&mut ffprobe_command, &sandbox::limits());
+	// The JSON isn't worth logging line by line.
+	let output =
+		run_os_command_with_stdout("ffprobe", ffprobe_command, |_| {})
+			.await?;
+	if !output.status.success() {
+		return Err(anyhow!(
+			"ffprobe couldn't read the file: {}",
+			String::from_utf8_lossy(&output.stderr).trim()
+		));
+	}
+	Ok(output.stdout)
+}
+
+/// Turns ffprobe's JSON into a [`MediaInfo`].
+pub fn parse(json: &[u8]) -> Result<MediaInfo> {
+	let output: ProbeOutput = serde_json::from_slice(json)
+		.context("Failed to read ffprobe's output")?;
+	Ok(MediaInfo {
+		container: output.format.format_name.unwrap_or_default(),
+		container_long_name: output.format.format_long_name,
+		duration: parse_duration(output.format.duration.as_deref()),
+		bit_rate: parse_number(output.format.bit_rate.as_deref()),
+		size: parse_number(output.format.size.as_deref()),
+		tags: output.format.tags,
+		streams: output.streams.into_iter().map(StreamInfo::from).collect(),
+	})
+}
+
+impl From<ProbeStream> for StreamInfo {
+	fn from(stream: ProbeStream) -> Self {
+		let kind = match stream.codec_type.as_deref() {
+			Some("video") => StreamKind::Video,
+			Some("audio") => StreamKind::Audio,
+			Some("subtitle") => StreamKind::Subtitle,
+			Some("data") => StreamKind::Data,
+			Some("attachment") => StreamKind::Attachment,
+			_ => StreamKind::Unknown,
+		};
+		let rotation = stream
+			.side_data_list
+			.iter()
+			.find_map(|side_data| side_data.rotation)
+			.or_else(|| {
+				stream
+					.tags
+					.get("rotate")
+					.and_then(|rotate| rotate.parse().ok())
+			});
+		// r_frame_rate is the container's guess, which is wrong for
+		// variable frame rates, so the average comes first.
+		let frame_rate = parse_rational(stream.avg_frame_rate.as_deref())
+			.or_else(|| parse_rational(stream.r_frame_rate.as_deref()));
+
+		Self {
+			index: stream.index,
+			kind,
+			codec: stream.codec_name,
+			codec_long_name: stream.codec_long_name,
+			profile: stream.profile,
+			width: stream.width,
+			height: stream.height,
+			rotation,
+			pixel_format: stream.pix_fmt,
+			bit_rate: parse_number(stream.bit_rate.as_deref()),
+			sample_rate: parse_number(stream.sample_rate.as_deref()),
+			channels: stream.channels,
+			channel_layout: stream.channel_layout,
+			frame_rate,
+			duration: parse_duration(stream.duration.as_deref()),
+			frames: parse_number(stream.nb_frames.as_deref()),
+			attached_pic: stream.disposition.attached_pic == 1,
+		}
+	}
+}
+
+/// ffprobe prints most numbers as strings, and `N/A` when it doesn't know.
+fn parse_number<T: std::str::FromStr>(value: Option<&str>) -> Option<T> {
+	value?.trim().parse().ok()
+}
+
+fn parse_duration(value: Option<&str>) -> Option<Duration> {
+	parse_number::<f64>(value)
+		.filter(|seconds| seconds.is_finite() && *seconds > 0.0)
+		.map(Duration::from_secs_f64)
+}
+
+/// Such as `30000/1001`. `0/0` means unknown.
+fn parse_rational(value: Option<&str>) -> Option<f64> {
+	let (numerator, denominator) = value?.split_once('/')?;
+	let numerator = numerator.parse::<f64>().ok()?;
+	let denominator = denominator.parse::<f64>().ok()?;
+	(numerator > 0.0 && denominator > 0.0).then(|| numerator / denominator)
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeOutput {
+	#[serde(default)]
+	format: ProbeFormat,
+	#[serde(default)]
+	streams: Vec<ProbeStream>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ProbeFormat {
+	format_name: Option<String>,
+	format_long_name: Option<String>,
+	duration: Option<String>,
+	bit_rate: Option<String>,
+	size: Option<String>,
+	#[serde(default)]
+	tags: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeStream {
+	index: u32,
+	codec_type: Option<String>,
+	codec_name: Option<String>,
+	codec_long_name: Option<String>,
+	profile: Option<String>,
+	width: Option<u32>,
+	height: Option<u32>,
+	pix_fmt: Option<String>,
+	bit_rate: Option<String>,
+	sample_rate: Option<String>,
+	channels: Option<u32>,
+	channel_layout: Option<String>,
+	avg_frame_rate: Option<String>,
+	r_frame_rate: Option<String>,
+	duration: Option<String>,
+	nb_frames: Option<String>,
+	#[serde(default)]
+	disposition: ProbeDisposition,
+	#[serde(default)]
+	side_data_list: Vec<ProbeSideData>,
+	#[serde(default)]
+	tags: HashMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ProbeDisposition {
+	#[serde(default)]
+	attached_pic: u8,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProbeSideData {
+	rotation: Option<i32>,
+}
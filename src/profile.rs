@@ -0,0 +1,146 @@
+use anyhow::{anyhow, Result};
+use poise::ChoiceParameter;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Color, Config};
+
+/// An owner's overrides for the config's personal settings.
+/// Anything left as `None` falls back to the config.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+	pub listenbrainz_user: Option<String>,
+	pub deepl_target_language: Option<String>,
+	pub timezone: Option<String>,
+	pub ephemeral: Option<bool>,
+	pub embed_color: Option<Color>,
+}
+impl Profile {
+	pub fn is_empty(&self) -> bool {
+		self.listenbrainz_user.is_none()
+			&& self.deepl_target_language.is_none()
+			&& self.timezone.is_none()
+			&& self.ephemeral.is_none()
+			&& self.embed_color.is_none()
+	}
+
+	/// Parses and validates `value` the same way the config does, then
+	/// stores it.
+	pub fn set(&mut self, field: &ProfileField, value: &str) -> Result<()> {
+		let value = value.trim();
+		match field {
+			ProfileField::ListenBrainzUser => {
+				if value.is_empty() {
+					return Err(anyhow!(
+						"The ListenBrainz user can't be empty."
+					));
+				}
+				self.listenbrainz_user = Some(value.to_string());
+			}
+			ProfileField::DeepLTargetLanguage => {
+				if value.is_empty() {
+					return Err(anyhow!(
+						"The DeepL target language can't be empty. Use the \
+						 language name as it appears on the DeepL website."
+					));
+				}
+				self.deepl_target_language = Some(value.to_string());
+			}
+			ProfileField::Timezone => {
+				let timezone =
+					value.parse::<chrono_tz::Tz>().map_err(|_| {
+						anyhow!(
+							"\"{}\" isn't a tz database time zone such as \
+							 \"America/New_York\".",
+							value
+						)
+					})?;
+				self.timezone = Some(timezone.name().to_string());
+			}
+			ProfileField::Ephemeral => {
+				self.ephemeral = Some(match value.to_lowercase().as_str() {
+					"true" | "yes" | "on" => true,
+					"false" | "no" | "off" => false,
+					_ => {
+						return Err(anyhow!(
+							"Ephemeral must be true or false, not \"{}\".",
+							value
+						))
+					}
+				});
+			}
+			ProfileField::EmbedColor => {
+				let color = value.parse::<Color>()?;
+				if color.0 > 0xFFFFFF {
+					return Err(anyhow!(
+						"0x{:X} isn't a 0xRRGGBB color.",
+						color.0
+					));
+				}
+				self.embed_color = Some(color);
+			}
+		}
+		Ok(())
+	}
+
+	pub fn reset(&mut self, field: &ProfileField) {
+		match field {
+			ProfileField::ListenBrainzUser => self.listenbrainz_user = None,
+			ProfileField::DeepLTargetLanguage => {
+				self.deepl_target_language = None
+			}
+			ProfileField::Timezone => self.timezone = None,
+			ProfileField::Ephemeral => self.ephemeral = None,
+			ProfileField::EmbedColor => self.embed_color = None,
+		}
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, ChoiceParameter)]
+pub enum ProfileField {
+	#[name = "ListenBrainz user"]
+	ListenBrainzUser,
+	#[name = "DeepL target language"]
+	DeepLTargetLanguage,
+	#[name = "Timezone"]
+	Timezone,
+	#[name = "Ephemeral"]
+	Ephemeral,
+	#[name = "Embed color"]
+	EmbedColor,
+}
+
+/// The settings a command should use for whoever ran it, which is their
+/// profile layered over the config.
+#[derive(Debug, Clone)]
+pub struct Settings {
+	pub listenbrainz_user: Option<String>,
+	pub deepl_target_language: String,
+	pub timezone: chrono_tz::Tz,
+	/// The default for commands that don't have one set in the config.
+	pub ephemeral: Option<bool>,
+	pub embed_color: Color,
+}
+impl Settings {
+	pub fn resolve(config: &Config, profile: Option<&Profile>) -> Self {
+		let profile = profile.cloned().unwrap_or_default();
+		Self {
+			listenbrainz_user: profile
+				.listenbrainz_user
+				.or_else(|| config.listenbrainz_user.clone()),
+			deepl_target_language: profile
+				.deepl_target_language
+				.unwrap_or_else(|| config.deepl_target_language.clone()),
+			// Both were validated when they were set.
+			timezone: profile
+				.timezone
+				.as_deref()
+				.unwrap_or(&config.timezone)
+				.parse()
+				.unwrap_or(chrono_tz::UTC),
+			ephemeral: profile.ephemeral,
+			embed_color: profile
+				.embed_color
+				.unwrap_or_else(|| config.embed_color.clone()),
+		}
+	}
+}
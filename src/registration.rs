@@ -0,0 +1,167 @@
+use anyhow::Result;
+use poise::serenity_prelude as serenity;
+use serde_json::Value;
+
+use crate::{commands::Command, config::Config};
+
+/// The top level command fields that Discord stores and that we set.
+/// Everything else (IDs, versions, localized names) is filled in by
+/// Discord and would make every comparison fail.
+const COMPARED_FIELDS: &[&str] = &[
+	"type",
+	"name",
+	"name_localizations",
+	"description",
+	"description_localizations",
+	"options",
+	"default_member_permissions",
+	"nsfw",
+	"integration_types",
+	"contexts",
+];
+
+/// Where application commands get registered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+	Global,
+	Guild(serenity::GuildId),
+}
+impl Scope {
+	/// Dev mode registers to the test guild so changes show up instantly.
+	pub fn from_config(config: &Config) -> Self {
+		match config.dev_guild_id {
+			Some(guild_id) => Self::Guild(guild_id),
+			None => Self::Global,
+		}
+	}
+}
+impl std::fmt::Display for Scope {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Global => write!(f, "globally"),
+			Self::Guild(guild_id) => write!(f, "in guild {}", guild_id),
+		}
+	}
+}
+
+/// Builds the JSON Discord expects for the given commands.
+pub fn command_definitions(
+	commands: &[Command],
+	scope: Scope,
+) -> Result<Vec<Value>> {
+	poise::builtins::create_application_commands(commands)
+		.iter()
+		.map(|command| {
+			let mut value = serde_json::to_value(command)?;
+			if let (Scope::Guild(_), Some(map)) =
+				(scope, value.as_object_mut())
+			{
+				// Discord only accepts these on global commands.
+				map.remove("integration_types");
+				map.remove("contexts");
+			}
+			Ok(value)
+		})
+		.collect()
+}
+
+pub async fn list(
+	http: &serenity::Http,
+	scope: Scope,
+) -> Result<Vec<serenity::Command>> {
+	Ok(match scope {
+		Scope::Global => http.get_global_commands().await?,
+		Scope::Guild(guild_id) => http.get_guild_commands(guild_id).await?,
+	})
+}
+
+pub async fn clear(http: &serenity::Http, scope: Scope) -> Result<()> {
+	overwrite(http, scope, &[]).await
+}
+
+/// Pushes the commands to Discord only if they differ from what's already
+/// registered. Returns whether anything was pushed.
+pub async fn sync(
+	http: &serenity::Http,
+	commands: &[Command],
+	scope: Scope,
+) -> Result<bool> {
+	let definitions = command_definitions(commands, scope)?;
+	let registered = list(http, scope)
+		.await?
+		.iter()
+		.map(serde_json::to_value)
+		.collect::<Result<Vec<_>, _>>()?;
+
+	if signatures(&definitions, scope) == signatures(&registered, scope) {
+		return Ok(false);
+	}
+
+	overwrite(http, scope, &definitions).await?;
+	Ok(true)
+}
+
+async fn overwrite(
+	http: &serenity::Http,
+	scope: Scope,
+	definitions: &[Value],
+) -> Result<()> {
+	match scope {
+		Scope::Global => {
+			http.create_global_commands(&definitions).await?;
+		}
+		Scope::Guild(guild_id) => {
+			http.create_guild_commands(guild_id, &definitions).await?;
+		}
+	}
+	Ok(())
+}
+
+/// Reduces command definitions to a sorted, comparable form.
+fn signatures(commands: &[Value], scope: Scope) -> Vec<Value> {
+	let mut signatures = commands
+		.iter()
+		.map(|command| {
+			let mut signature = serde_json::Map::new();
+			for &field in COMPARED_FIELDS {
+				if matches!(scope, Scope::Guild(_))
+					&& matches!(field, "integration_types" | "contexts")
+				{
+					continue;
+				}
+				if let Some(value) = command.get(field).and_then(normalize) {
+					signature.insert(field.to_string(), value);
+				}
+			}
+			// Chat input is the default type and may be left out.
+			signature.entry("type").or_insert(Value::from(1));
+			Value::Object(signature)
+		})
+		.collect::<Vec<_>>();
+	signatures.sort_by_key(|signature| {
+		(signature["type"].to_string(), signature["name"].to_string())
+	});
+	signatures
+}
+
+/// Drops the values Discord treats the same as a missing field, so that
+/// `"required": false` matches a missing `required` and so on.
+fn normalize(value: &Value) -> Option<Value> {
+	match value {
+		Value::Null | Value::Bool(false) => None,
+		Value::String(s) if s.is_empty() => None,
+		Value::Array(items) => {
+			let items =
+				items.iter().filter_map(normalize).collect::<Vec<_>>();
+			(!items.is_empty()).then_some(Value::Array(items))
+		}
+		Value::Object(map) => {
+			let map = map
+				.iter()
+				.filter_map(|(k, v)| normalize(v).map(|v| (k.clone(), v)))
+				.collect::<serde_json::Map<_, _>>();
+			(!map.is_empty()).then_some(Value::Object(map))
+		}
+		_ => Some(value.clone()),
+	}
+}
@@ -0,0 +1,368 @@
+use std::{
+	path::Path,
+	process,
+	sync::{LazyLock, RwLock},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::config::{Config, FfmpegLimits};
+
+// ffmpeg can read and write any path and open network protocols from nearly
+// any argument, including filter options like `fontfile=`. So every argument
+// from a user is checked, the process runs inside its own job directory, and
+// the OS limits what it can use. None of this is a real sandbox, just a
+// best effort at keeping ffmpeg inside the job directory.
+
+/// The protocols ffmpeg may open, passed to `-protocol_whitelist`. Any other
+/// `scheme:` in an argument is rejected.
+pub const ALLOWED_PROTOCOLS: &[&str] = &["file", "pipe"];
+
+/// The configured limits, updated whenever the config reloads.
+static LIMITS: LazyLock<RwLock<FfmpegLimits>> =
+	LazyLock::new(|| RwLock::new(FfmpegLimits::default()));
+
+/// Options that would undo the sandbox.
+const FORBIDDEN_OPTIONS: &[&str] = &[
+	"-protocol_whitelist",
+	"-protocol_blacklist",
+	// Lets the concat demuxer read absolute paths from a list file.
+	"-safe",
+	// The bot reads the progress from stdout.
+	"-progress",
+];
+
+/// Filters that open files or connections on their own, without the
+/// protocol whitelist.
+const FORBIDDEN_FILTERS: &[&str] = &["movie", "amovie", "zmq", "azmq"];
+
+/// Where a file name or path can start inside an argument, such as after
+/// `=` in `movie=input.mp4` or the quotes around a filter option.
+const SEPARATORS: &[char] = &[
+	'=', ',', ';', '|', '\'', '"', '[', ']', '(', ')', '{', '}', '<', '>',
+];
+
+/// What separates the values in a filtergraph, outside of quotes.
+const VALUE_SEPARATORS: &[char] = &['=', ',', ';', ':', '[', ']'];
+
+/// Checks the user's flags, returning the reason for the first argument
+/// that could reach outside the job directory.
+pub fn check_ffmpeg_args<'a>(
+	args: impl IntoIterator<Item = &'a str>,
+) -> Result<()> {
+	for arg in args {
+		check_option(arg)?;
+		// Inputs and outputs are opened as a whole, while filtergraphs and
+		// option lists hide their values between separators.
+		check_protocol(arg, arg)?;
+		for (before, value) in filter_values(arg) {
+			check_value(arg, before, &value)?;
+		}
+		for piece in arg.split(SEPARATORS).filter(|piece| !piece.is_empty()) {
+			// Filter options are separated by `:` so paths can hide
+			// after one, as in `drawtext=text=a:fontfile=/etc/passwd`.
+			for part in piece.split(':') {
+				check_path(arg, part)?;
+			}
+		}
+	}
+	Ok(())
+}
+
+fn check_option(arg: &str) -> Result<()> {
+	let option = arg.split(':').next().unwrap_or(arg);
+	if FORBIDDEN_OPTIONS.contains(&option) {
+		return Err(anyhow!("`{}` isn't allowed.", arg));
+	}
+	// `-/filter file` and `-filter_script file` read the value from a file.
+	if option.starts_with("-/")
+		|| option.starts_with('-') && option.ends_with("_script")
+	{
+		return Err(anyhow!(
+			"`{}` reads its value from a file, which isn't allowed.",
+			arg
+		));
+	}
+	Ok(())
+}
+
+/// Splits an argument into its filtergraph values the way ffmpeg does, with
+/// `'` quotes and `\` escapes, along with the separator before each one.
+fn filter_values(arg: &str) -> Vec<(Option<char>, String)> {
+	let mut values = Vec::new();
+	let mut before = None;
+	let mut value = String::new();
+	let mut quoted = false;
+	let mut chars = arg.chars();
+	while let Some(char) = chars.next() {
+		match char {
+			'\'' => quoted = !quoted,
+			'\\' if !quoted => value.extend(chars.next()),
+			char if !quoted && VALUE_SEPARATORS.contains(&char) => {
+				values.push((before, std::mem::take(&mut value)));
+				before = Some(char);
+			}
+			char => value.push(char),
+		}
+	}
+	values.push((before, value));
+	values
+}
+
+fn check_value(arg: &str, before: Option<char>, value: &str) -> Result<()> {
+	// Filters unescape their options again, so what's left of the quotes
+	// and escapes doesn't count.
+	let value = value
+		.chars()
+		.filter(|char| !matches!(char, '\\' | '\''))
+		.collect::<String>();
+	let value = value.trim();
+
+	// A filter's name comes first or after a `,`, `;` or label, and can
+	// have an `@instance` after it.
+	if matches!(before, None | Some(',' | ';' | ']')) {
+		let filter = value.split('@').next().unwrap_or(value);
+		if FORBIDDEN_FILTERS.contains(&filter) {
+			return Err(anyhow!(
+				"`{}` uses the {} filter, which isn't allowed.",
+				arg,
+				filter
+			));
+		}
+	}
+	// Escapes hide a `..` from the raw argument, as in
+	// `subtitles=.\./secret.srt`, so the unescaped value is checked too.
+	for part in value.split(':') {
+		check_path(arg, part)?;
+	}
+	check_protocol(arg, value)
+}
+
+fn check_protocol(arg: &str, value: &str) -> Result<()> {
+	let Some((scheme, rest)) = url_scheme(value) else {
+		return Ok(());
+	};
+	if !ALLOWED_PROTOCOLS.contains(&scheme) {
+		return Err(anyhow!(
+			"`{}` uses the {} protocol, but only {} are allowed.",
+			arg,
+			scheme,
+			ALLOWED_PROTOCOLS.join(" and ")
+		));
+	}
+	check_path(arg, rest)
+}
+
+/// Splits `scheme:rest` the way ffmpeg finds a URL's protocol, which also
+/// takes `subfile,` as one.
+fn url_scheme(value: &str) -> Option<(&str, &str)> {
+	if let Some(rest) = value.strip_prefix("subfile,") {
+		return Some(("subfile", rest));
+	}
+	let end = value
+		.find(|char: char| {
+			!char.is_ascii_alphanumeric() && !matches!(char, '+' | '-' | '.')
+		})
+		.unwrap_or(value.len());
+	let (scheme, rest) = value.split_at(end);
+	let rest = rest.strip_prefix(':')?;
+	// Times like `00:01:30` and stream specifiers like `0:a` aren't URLs.
+	scheme
+		.starts_with(|char: char| char.is_ascii_alphabetic())
+		.then_some((scheme, rest))
+}
+
+fn check_path(arg: &str, part: &str) -> Result<()> {
+	// Drive letters are caught too since `C:\` is split at the `:`.
+	let is_absolute = part.starts_with(['/', '\\', '~']);
+	let escapes = part.split(['/', '\\']).any(|component| component == "..");
+	if is_absolute || escapes {
+		return Err(anyhow!(
+			"`{}` refers to a file outside the job directory.",
+			arg
+		));
+	}
+	Ok(())
+}
+
+/// Checks that the output is a plain file name that stays in the job
+/// directory.
+pub fn check_output_name(output_name: &str) -> Result<()> {
+	let is_plain = Path::new(output_name).file_name()
+		== Some(output_name.as_ref())
+		&& !output_name.contains(['/', '\\', ':'])
+		&& !output_name.chars().any(char::is_control);
+	if !is_plain || output_name.starts_with(['.', '-']) {
+		return Err(anyhow!(
+			"The output name must be a file name such as `output.mp4`, \
+			 without a path."
+		));
+	}
+	if output_name.len() > 255 {
+		return Err(anyhow!("The output name is too long."));
+	}
+	Ok(())
+}
+
+/// Remembers the ffmpeg limits from the config. Called again whenever the
+/// config reloads.
+pub fn set_limits(config: &Config) {
+	*LIMITS.write().unwrap_or_else(|e| e.into_inner()) =
+		config.ffmpeg_limits.clone();
+}
+
+/// The limits every ffmpeg process runs with.
+pub fn limits() -> FfmpegLimits {
+	LIMITS.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Applies the CPU time, memory and file size limits to the process once
+/// it starts.
+#[cfg(unix)]
+pub fn limit_resources(
+	command: &mut process::Command,
+	limits: &FfmpegLimits,
+) {
+	use std::os::unix::process::CommandExt;
+
+	const MB: u64 = 1024 * 1024;
+	let cpu_seconds = limits.cpu_seconds;
+	let memory = limits.memory_mb.saturating_mul(MB);
+	let output_file = limits.output_file_mb.saturating_mul(MB);
+
+	// SAFETY: This runs between fork and exec, where only async-signal-safe
+	// functions may be called. setrlimit is one, and nothing allocates.
+	unsafe {
+		command.pre_exec(move || {
+			let set_limit = |resource, soft: u64, hard: u64| {
+				let limit = libc::rlimit {
+					rlim_cur: soft as libc::rlim_t,
+					rlim_max: hard as libc::rlim_t,
+				};
+				if libc::setrlimit(resource, &limit) == 0 {
+					Ok(())
+				} else {
+					Err(std::io::Error::last_os_error())
+				}
+			};
+			// The soft limit sends SIGXCPU, and the hard one is a SIGKILL
+			// for anything that ignores it.
+			set_limit(libc::RLIMIT_CPU, cpu_seconds, cpu_seconds + 5)?;
+			set_limit(libc::RLIMIT_AS, memory, memory)?;
+			set_limit(libc::RLIMIT_FSIZE, output_file, output_file)?;
+			Ok(())
+		});
+	}
+}
+
+#[cfg(not(unix))]
+pub fn limit_resources(
+	_command: &mut process::Command,
+	_limits: &FfmpegLimits,
+) {
+}
+
+/// Explains an exit caused by one of the limits.
+pub fn limit_exceeded(
+	status: &process::ExitStatus,
+	limits: &FfmpegLimits,
+) -> Option<String> {
+	#[cfg(unix)]
+	{
+		use std::os::unix::process::ExitStatusExt;
+
+		match status.signal()? {
+			libc::SIGXCPU => Some(format!(
+				"ffmpeg used more than {} seconds of CPU time.",
+				limits.cpu_seconds
+			)),
+			libc::SIGXFSZ => Some(format!(
+				"ffmpeg tried to write a file larger than {} MB.",
+				limits.output_file_mb
+			)),
+			_ => None,
+		}
+	}
+	#[cfg(not(unix))]
+	{
+		let _ = (status, limits);
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn check(args: &[&str]) -> Result<()> {
+		check_ffmpeg_args(args.iter().copied())
+	}
+
+	fn assert_rejected(args: &[&str]) {
+		assert!(check(args).is_err(), "{:?} was accepted", args);
+	}
+
+	#[test]
+	fn accepts_arguments_inside_the_job_directory() {
+		for args in [
+			&["-i", "input.mp4", "output.webm"][..],
+			&["-vf", "scale=640:-2,fps=30"],
+			&["-vf", "subtitles=subs/english.srt"],
+			&["-vf", "drawtext=text='Hello, world':fontsize=24"],
+			&["-ss", "00:01:30", "-map", "0:a"],
+			&["-i", "file:input.mp4"],
+		] {
+			check(args).unwrap_or_else(|e| panic!("{:?}: {}", args, e));
+		}
+	}
+
+	#[test]
+	fn rejects_parent_directories() {
+		assert_rejected(&["-i", "../secret.mp4"]);
+		assert_rejected(&["-vf", "subtitles=subs/../../secret.srt"]);
+		assert_rejected(&["-vf", "drawtext=text=a:fontfile=..\\font.ttf"]);
+	}
+
+	#[test]
+	fn rejects_escaped_parent_directories() {
+		assert_rejected(&["-vf", "subtitles=.\\./.\\./.\\./etc/passwd"]);
+		assert_rejected(&["-vf", "subtitles=\\.\\.\\/secret.srt"]);
+		assert_rejected(&["-vf", "subtitles=.\\\\\\./secret.srt"]);
+	}
+
+	#[test]
+	fn rejects_quoted_parent_directories() {
+		assert_rejected(&["-vf", "subtitles='../secret.srt'"]);
+		assert_rejected(&["-vf", "subtitles='.'.'/secret.srt'"]);
+		assert_rejected(&["-vf", "drawtext=text=a:fontfile='..'/font.ttf"]);
+	}
+
+	#[test]
+	fn rejects_file_urls_outside_the_job_directory() {
+		assert_rejected(&["-i", "file:../secret.mp4"]);
+		assert_rejected(&["-i", "file:/etc/passwd"]);
+		assert_rejected(&["-vf", "subtitles=file\\:../secret.srt"]);
+		assert_rejected(&["-vf", "subtitles='file:/etc/passwd'"]);
+		assert_rejected(&["-vf", "subtitles=file\\:.\\./secret.srt"]);
+	}
+
+	#[test]
+	fn rejects_absolute_paths() {
+		assert_rejected(&["-i", "/etc/passwd"]);
+		assert_rejected(&["-i", "~/secret.mp4"]);
+		assert_rejected(&["-vf", "drawtext=text=a:fontfile=/etc/passwd"]);
+		assert_rejected(&["-vf", "subtitles='/etc/passwd'"]);
+		assert_rejected(&["-vf", "subtitles=\\/etc/passwd"]);
+		assert_rejected(&["-vf", "subtitles=\\\\/etc/passwd"]);
+		assert_rejected(&["-vf", "subtitles=C\\:\\\\Windows\\\\win.ini"]);
+	}
+
+	#[test]
+	fn rejects_other_protocols_and_filters() {
+		assert_rejected(&["-i", "http://example.com/video.mp4"]);
+		assert_rejected(&["-vf", "subtitles='http://example.com/subs.srt'"]);
+		assert_rejected(&["-vf", "movie=input.mp4[logo];[0][logo]overlay"]);
+		assert_rejected(&["-protocol_whitelist", "file,http"]);
+		assert_rejected(&["-filter_script:v", "graph.txt"]);
+	}
+}
@@ -0,0 +1,276 @@
+use std::{fs::File, io::Read, path::Path};
+
+use crate::probe::{MediaInfo, MediaType};
+
+/// How much of the file is read to sniff it. APNG's `acTL` chunk comes
+/// after any `iCCP` profile, which can be tens of kilobytes.
+const SNIFF_LENGTH: u64 = 64 * 1024;
+
+/// What a file actually is, whatever its name says.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+	Png,
+	Apng,
+	Gif,
+	Jpeg,
+	WebP,
+	Avif,
+	Heic,
+	Jxl,
+	Bmp,
+	Tiff,
+	Mp4,
+	Mov,
+	WebM,
+	Mkv,
+	Avi,
+	Mp3,
+	Aac,
+	M4a,
+	Ogg,
+	Opus,
+	Flac,
+	Wav,
+}
+
+impl FileFormat {
+	/// The usual extension, without the dot.
+	pub fn extension(&self) -> &'static str {
+		match self {
+			// Discord only animates APNGs named `.png`.
+			Self::Png | Self::Apng => "png",
+			Self::Gif => "gif",
+			Self::Jpeg => "jpg",
+			Self::WebP => "webp",
+			Self::Avif => "avif",
+			Self::Heic => "heic",
+			Self::Jxl => "jxl",
+			Self::Bmp => "bmp",
+			Self::Tiff => "tiff",
+			Self::Mp4 => "mp4",
+			Self::Mov => "mov",
+			Self::WebM => "webm",
+			Self::Mkv => "mkv",
+			Self::Avi => "avi",
+			Self::Mp3 => "mp3",
+			Self::Aac => "aac",
+			Self::M4a => "m4a",
+			Self::Ogg => "ogg",
+			Self::Opus => "opus",
+			Self::Flac => "flac",
+			Self::Wav => "wav",
+		}
+	}
+
+	/// Whether `extension` is one this format is commonly named with, such
+	/// as `jpeg` for JPEGs or `m4a` for audio-only MP4s.
+	pub fn matches_extension(&self, extension: &str) -> bool {
+		let extension = extension.to_lowercase();
+		if extension == self.extension() {
+			return true;
+		}
+		let aliases: &[&str] = match self {
+			Self::Jpeg => &["jpeg", "jpe", "jfif"],
+			Self::Tiff => &["tif"],
+			Self::Heic => &["heif"],
+			Self::Mp4 => &["m4v", "m4a"],
+			Self::M4a => &["mp4", "m4b"],
+			Self::Ogg => &["oga", "opus"],
+			Self::Opus => &["ogg", "oga"],
+			_ => &[],
+		};
+		aliases.contains(&extension.as_str())
+	}
+
+	/// Reads ffprobe's container names, such as `png_pipe` or
+	/// `mov,mp4,m4a,3gp,3g2,mj2`, for files the magic bytes didn't
+	/// cover.
+	pub fn from_media_info(info: &MediaInfo) -> Option<Self> {
+		let names = info.container.split(',').collect::<Vec<_>>();
+		let has = |name: &str| names.contains(&name);
+		let codecs = info
+			.streams
+			.iter()
+			.filter_map(|stream| stream.codec.as_deref())
+			.collect::<Vec<_>>();
+		Some(if has("apng") {
+			Self::Apng
+		} else if has("png_pipe") {
+			Self::Png
+		} else if has("gif") || has("gif_pipe") {
+			Self::Gif
+		} else if has("jpeg_pipe") || has("mjpeg") {
+			Self::Jpeg
+		} else if has("webp_pipe") {
+			Self::WebP
+		} else if has("jpegxl_pipe") || has("jpegxl_anim") {
+			Self::Jxl
+		} else if has("bmp_pipe") {
+			Self::Bmp
+		} else if has("tiff_pipe") {
+			Self::Tiff
+		} else if has("matroska") {
+			let webm_codecs = ["vp8", "vp9", "av1", "opus", "vorbis"];
+			if codecs.iter().all(|codec| webm_codecs.contains(codec)) {
+				Self::WebM
+			} else {
+				Self::Mkv
+			}
+		} else if has("mov") {
+			match info.media_type() {
+				MediaType::Audio => Self::M4a,
+				_ => Self::Mp4,
+			}
+		} else if has("avi") {
+			Self::Avi
+		} else if has("mp3") {
+			Self::Mp3
+		} else if has("aac") {
+			Self::Aac
+		} else if has("ogg") {
+			if codecs == ["opus"] {
+				Self::Opus
+			} else {
+				Self::Ogg
+			}
+		} else if has("flac") {
+			Self::Flac
+		} else if has("wav") {
+			Self::Wav
+		} else {
+			return None;
+		})
+	}
+}
+
+/// Works out the format from the file's first bytes. ffprobe's
+/// [`FileFormat::from_media_info`] covers the rest.
+pub fn sniff_file(path: &Path) -> Option<FileFormat> {
+	sniff(&read_header(path).ok()?)
+}
+
+fn read_header(path: &Path) -> std::io::Result<Vec<u8>> {
+	let mut header = Vec::new();
+	File::open(path)?
+		.take(SNIFF_LENGTH)
+		.read_to_end(&mut header)?;
+	Ok(header)
+}
+
+/// Recognizes a format from its magic bytes.
+pub fn sniff(header: &[u8]) -> Option<FileFormat> {
+	let at = |offset: usize, magic: &[u8]| {
+		header.get(offset..offset + magic.len()) == Some(magic)
+	};
+
+	if at(0, b"\x89PNG\r\n\x1a\n") {
+		return Some(if png_is_animated(header) {
+			FileFormat::Apng
+		} else {
+			FileFormat::Png
+		});
+	}
+	if at(0, b"GIF87a") || at(0, b"GIF89a") {
+		return Some(FileFormat::Gif);
+	}
+	if at(0, b"\xff\xd8\xff") {
+		return Some(FileFormat::Jpeg);
+	}
+	if at(0, b"RIFF") {
+		return match header.get(8..12)? {
+			b"WEBP" => Some(FileFormat::WebP),
+			b"WAVE" => Some(FileFormat::Wav),
+			b"AVI " => Some(FileFormat::Avi),
+			_ => None,
+		};
+	}
+	if at(4, b"ftyp") {
+		// The major brand, then the minor version, then compatible brands.
+		// AVIFs often have `mif1` as the major brand and list `avif` later.
+		let size = u32::from_be_bytes(header.get(0..4)?.try_into().ok()?);
+		let brands = header.get(8..(size as usize).min(header.len()))?;
+		let has_brand = |brand: &[u8]| {
+			brands
+				.chunks(4)
+				.enumerate()
+				.any(|(index, chunk)| index != 1 && chunk == brand)
+		};
+		return Some(if has_brand(b"avif") || has_brand(b"avis") {
+			FileFormat::Avif
+		} else {
+			match brands.get(0..4)? {
+				b"heic" | b"heix" | b"heim" | b"heis" | b"mif1" | b"msf1" => {
+					FileFormat::Heic
+				}
+				b"qt  " => FileFormat::Mov,
+				b"M4A " | b"M4B " => FileFormat::M4a,
+				_ => FileFormat::Mp4,
+			}
+		});
+	}
+	if at(0, b"\xff\x0a") || at(0, b"\0\0\0\x0cJXL \r\n\x87\n") {
+		return Some(FileFormat::Jxl);
+	}
+	if at(0, b"\x1a\x45\xdf\xa3") {
+		// The DocType comes right after the EBML header's start.
+		let doc_type = &header[..header.len().min(64)];
+		return Some(if contains(doc_type, b"webm") {
+			FileFormat::WebM
+		} else {
+			FileFormat::Mkv
+		});
+	}
+	if at(0, b"OggS") {
+		// The first page holds the codec's identification header.
+		return Some(if at(28, b"OpusHead") {
+			FileFormat::Opus
+		} else {
+			FileFormat::Ogg
+		});
+	}
+	if at(0, b"fLaC") {
+		return Some(FileFormat::Flac);
+	}
+	if at(0, b"ID3") {
+		return Some(FileFormat::Mp3);
+	}
+	if at(0, b"BM") && header.len() >= 14 {
+		return Some(FileFormat::Bmp);
+	}
+	if at(0, b"II*\0") || at(0, b"MM\0*") {
+		return Some(FileFormat::Tiff);
+	}
+	match header {
+		// ADTS has a frame sync with the layer bits cleared.
+		[0xff, second, ..] if second & 0xf6 == 0xf0 => Some(FileFormat::Aac),
+		// MPEG audio has a frame sync with a layer set.
+		[0xff, second, ..] if second & 0xe0 == 0xe0 && second & 0x06 != 0 => {
+			Some(FileFormat::Mp3)
+		}
+		_ => None,
+	}
+}
+
+/// Whether a PNG has an `acTL` chunk before its image data, which is what
+/// makes it an APNG.
+fn png_is_animated(header: &[u8]) -> bool {
+	let mut offset = 8;
+	while let Some(chunk) = header.get(offset..offset + 8) {
+		let length =
+			u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+		match &chunk[4..8] {
+			b"acTL" => return true,
+			b"IDAT" => return false,
+			_ => {}
+		}
+		// The length, type, data and CRC.
+		offset += 12 + length as usize;
+	}
+	false
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+	haystack
+		.windows(needle.len())
+		.any(|window| window == needle)
+}
@@ -0,0 +1,330 @@
+use std::{
+	fs,
+	num::NonZeroU64,
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context as _, Result};
+use poise::serenity_prelude::UserId;
+use rusqlite::{params, Connection, OptionalExtension};
+use tracing::info;
+
+use crate::{
+	config::Color,
+	history::{HistoryEntry, Outcome},
+	profile::Profile,
+};
+
+/// Every schema change gets a new entry at the end. Entries are never edited
+/// once released because `user_version` records how many have been applied.
+const MIGRATIONS: &[&str] = &[
+	// 1: Per-owner settings. NULL falls back to the config.
+	"CREATE TABLE profiles (
+		user_id INTEGER PRIMARY KEY,
+		listenbrainz_user TEXT,
+		deepl_target_language TEXT,
+		timezone TEXT,
+		ephemeral INTEGER,
+		embed_color INTEGER
+	);",
+	// 2: Command invocations for /history.
+	"CREATE TABLE history (
+		id INTEGER PRIMARY KEY AUTOINCREMENT,
+		user_id INTEGER NOT NULL,
+		command TEXT NOT NULL,
+		data TEXT NOT NULL,
+		invoked_at INTEGER NOT NULL,
+		duration_ms INTEGER NOT NULL,
+		outcome TEXT NOT NULL,
+		error TEXT
+	);
+	CREATE INDEX history_user ON history (user_id, id);",
+];
+
+/// The bot's SQLite database.
+/// Every call runs on the blocking thread pool so commands can await it.
+#[derive(Debug, Clone)]
+pub struct Storage {
+	connection: Arc<Mutex<Connection>>,
+	path: PathBuf,
+}
+
+impl Storage {
+	/// Opens or creates the database and brings its schema up to date.
+	pub async fn open(path: impl Into<PathBuf>) -> Result<Self> {
+		let path = path.into();
+		let connection = {
+			let path = path.clone();
+			tokio::task::spawn_blocking(move || open_and_migrate(&path))
+				.await??
+		};
+		Ok(Self {
+			connection: Arc::new(Mutex::new(connection)),
+			path,
+		})
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Runs `f` with the connection on the blocking thread pool.
+	/// The typed methods are built on this; commands should prefer them.
+	pub async fn call<T, F>(&self, f: F) -> Result<T>
+	where
+		T: Send + 'static,
+		F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+	{
+		let connection = self.connection.clone();
+		tokio::task::spawn_blocking(move || {
+			let mut connection =
+				connection.lock().unwrap_or_else(|e| e.into_inner());
+			f(&mut connection)
+		})
+		.await?
+		.map_err(Into::into)
+	}
+
+	pub async fn profiles(&self) -> Result<Vec<(UserId, Profile)>> {
+		self.call(|connection| {
+			connection
+				.prepare(
+					"SELECT user_id, listenbrainz_user, deepl_target_language,
+					 timezone, ephemeral, embed_color FROM profiles",
+				)?
+				.query_map([], |row| {
+					Ok((
+						user_id_from_row(row, 0)?,
+						Profile {
+							listenbrainz_user: row.get(1)?,
+							deepl_target_language: row.get(2)?,
+							timezone: row.get(3)?,
+							ephemeral: row.get(4)?,
+							embed_color: row.get::<_, Option<u32>>(5)?.map(Color),
+						},
+					))
+				})?
+				.collect()
+		})
+		.await
+	}
+
+	/// Saves the whole profile. Empty profiles are deleted.
+	pub async fn save_profile(
+		&self,
+		user_id: UserId,
+		profile: &Profile,
+	) -> Result<()> {
+		let user_id = user_id.get() as i64;
+		if profile.is_empty() {
+			self.call(move |connection| {
+				connection.execute(
+					"DELETE FROM profiles WHERE user_id = ?1",
+					params![user_id],
+				)
+			})
+			.await?;
+			return Ok(());
+		}
+
+		let profile = profile.clone();
+		self.call(move |connection| {
+			connection.execute(
+				"INSERT OR REPLACE INTO profiles
+				 (user_id, listenbrainz_user, deepl_target_language,
+				 timezone, ephemeral, embed_color)
+				 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+				params![
+					user_id,
+					profile.listenbrainz_user,
+					profile.deepl_target_language,
+					profile.timezone,
+					profile.ephemeral,
+					profile.embed_color.map(|color| color.0),
+				],
+			)
+		})
+		.await?;
+		Ok(())
+	}
+
+	pub async fn add_history(&self, entry: &HistoryEntry) -> Result<()> {
+		let entry = entry.clone();
+		let data = serde_json::to_string(&entry.data)?;
+		self.call(move |connection| {
+			connection.execute(
+				"INSERT INTO history
+				 (user_id, command, data, invoked_at, duration_ms, outcome,
+				 error)
+				 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+				params![
+					entry.user_id.get() as i64,
+					entry.command,
+					data,
+					entry.invoked_at,
+					entry.duration.as_millis() as i64,
+					entry.outcome.as_str(),
+					entry.error,
+				],
+			)
+		})
+		.await?;
+		Ok(())
+	}
+
+	/// The user's most recent invocations, newest first.
+	pub async fn history(
+		&self,
+		user_id: UserId,
+		filter: &HistoryFilter,
+	) -> Result<Vec<HistoryEntry>> {
+		let user_id = user_id.get() as i64;
+		let command = filter
+			.command
+			.as_ref()
+			.map(|command| command.trim_start_matches('/').to_lowercase());
+		let outcome = filter.outcome.map(|outcome| outcome.as_str());
+		let limit = filter.limit;
+		self.call(move |connection| {
+			connection
+				.prepare(
+					"SELECT id, user_id, command, data, invoked_at,
+					 duration_ms, outcome, error FROM history
+					 WHERE user_id = ?1
+					 AND (?2 IS NULL OR lower(command) = ?2)
+					 AND (?3 IS NULL OR outcome = ?3)
+					 ORDER BY id DESC LIMIT ?4",
+				)?
+				.query_map(
+					params![user_id, command, outcome, limit],
+					history_entry_from_row,
+				)?
+				.collect()
+		})
+		.await
+	}
+
+	/// One of the user's invocations. Other users' entries aren't returned.
+	pub async fn history_entry(
+		&self,
+		user_id: UserId,
+		id: i64,
+	) -> Result<Option<HistoryEntry>> {
+		let user_id = user_id.get() as i64;
+		self.call(move |connection| {
+			connection
+				.query_row(
+					"SELECT id, user_id, command, data, invoked_at,
+					 duration_ms, outcome, error FROM history
+					 WHERE user_id = ?1 AND id = ?2",
+					params![user_id, id],
+					history_entry_from_row,
+				)
+				.optional()
+		})
+		.await
+	}
+}
+
+/// Narrows down `/history`. Empty fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+	/// Case-insensitive, with or without the leading slash.
+	pub command: Option<String>,
+	pub outcome: Option<Outcome>,
+	pub limit: u32,
+}
+
+fn history_entry_from_row(
+	row: &rusqlite::Row,
+) -> rusqlite::Result<HistoryEntry> {
+	let data = row.get::<_, String>(3)?;
+	let outcome = row.get::<_, String>(6)?;
+	Ok(HistoryEntry {
+		id: row.get(0)?,
+		user_id: user_id_from_row(row, 1)?,
+		command: row.get(2)?,
+		data: serde_json::from_str(&data).map_err(|e| {
+			rusqlite::Error::FromSqlConversionFailure(
+				3,
+				rusqlite::types::Type::Text,
+				Box::new(e),
+			)
+		})?,
+		invoked_at: row.get(4)?,
+		duration: Duration::from_millis(row.get::<_, i64>(5)? as u64),
+		outcome: Outcome::parse(&outcome).unwrap_or(Outcome::Error),
+		error: row.get(7)?,
+	})
+}
+
+/// A zero user ID would panic in [`UserId::new`], so a corrupt row is an
+/// error instead.
+fn user_id_from_row(
+	row: &rusqlite::Row,
+	index: usize,
+) -> rusqlite::Result<UserId> {
+	let id = row.get::<_, i64>(index)?;
+	NonZeroU64::new(id as u64).map(UserId::from).ok_or_else(|| {
+		rusqlite::Error::FromSqlConversionFailure(
+			index,
+			rusqlite::types::Type::Integer,
+			format!("{} isn't a user ID", id).into(),
+		)
+	})
+}
+
+fn open_and_migrate(path: &Path) -> Result<Connection> {
+	if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty())
+	{
+		fs::create_dir_all(parent).with_context(|| {
+			format!("Failed to create {}", parent.display())
+		})?;
+	}
+	let mut connection = Connection::open(path)
+		.with_context(|| format!("Failed to open {}", path.display()))?;
+	connection.pragma_update(None, "journal_mode", "WAL")?;
+	connection.pragma_update(None, "foreign_keys", true)?;
+	connection.busy_timeout(Duration::from_secs(5))?;
+	migrate(&mut connection)
+		.with_context(|| format!("Failed to migrate {}", path.display()))?;
+	Ok(connection)
+}
+
+/// Applies the migrations the database hasn't seen yet, each in its own
+/// transaction so a failure leaves the last good version behind.
+fn migrate(connection: &mut Connection) -> Result<()> {
+	let version =
+		connection.pragma_query_value(None, "user_version", |row| {
+			row.get::<_, usize>(0)
+		})?;
+	if version > MIGRATIONS.len() {
+		return Err(anyhow!(
+			"The database is at version {} but this build only knows {}. \
+			 Was it opened by a newer version of the bot?",
+			version,
+			MIGRATIONS.len()
+		));
+	}
+
+	for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+		let version = index + 1;
+		let transaction = connection.transaction()?;
+		transaction
+			.execute_batch(migration)
+			.with_context(|| format!("Migration {} failed", version))?;
+		transaction.pragma_update(None, "user_version", version)?;
+		transaction.commit()?;
+		info!("Applied database migration {}.", version);
+	}
+	Ok(())
+}
+
+/// Unix seconds, which is how timestamps are stored.
+pub fn unix_now() -> i64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |duration| duration.as_secs() as i64)
+}
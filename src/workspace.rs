@@ -0,0 +1,187 @@
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context as _, Result};
+use tempfile::TempDir;
+
+use crate::{
+	helpers::{change_extension, AttachmentOrThumbnail},
+	probe::{self, MediaInfo},
+	sniff::{self, FileFormat},
+};
+
+/// The longest file name kept, in bytes. Most filesystems allow 255.
+const MAX_FILENAME_LENGTH: usize = 200;
+
+/// A unique temporary directory for one job's files.
+///
+/// Inputs and outputs get their own subdirectories so converting
+/// `image.webp` to WebP can't overwrite its input. Everything is deleted
+/// when the workspace is dropped, including after an early return.
+#[derive(Debug)]
+pub struct Workspace {
+	dir: TempDir,
+}
+
+impl Workspace {
+	/// `job` only names the directory to make it easier to spot.
+	pub fn new(job: &str) -> Result<Self> {
+		let dir = tempfile::Builder::new()
+			.prefix(&format!("selfbot-{}-", sanitize_filename(job)))
+			.tempdir()
+			.context("Failed to create a job directory")?;
+		fs::create_dir(dir.path().join("input"))?;
+		fs::create_dir(dir.path().join("output"))?;
+		Ok(Self { dir })
+	}
+
+	pub fn path(&self) -> &Path {
+		self.dir.path()
+	}
+
+	/// Where an input named `filename` goes, after sanitizing it.
+	pub fn input_path(&self, filename: &str) -> PathBuf {
+		self.path().join("input").join(sanitize_filename(filename))
+	}
+
+	/// Where an output named `filename` goes, after sanitizing it.
+	pub fn output_path(&self, filename: &str) -> PathBuf {
+		self.path().join("output").join(sanitize_filename(filename))
+	}
+
+	/// Downloads the attachment into the inputs and works out what it
+	/// really is. Attachments with the same name are numbered instead of
+	/// replaced, and the extension is fixed to match the contents, since
+	/// Discord's names and proxy URLs can't be trusted and tools pick
+	/// decoders by extension.
+	///
+	/// ffprobe runs once here, settling whatever the magic bytes don't, so
+	/// callers should use [`Download::info`] instead of probing again.
+	pub async fn download(
+		&self,
+		client: &reqwest::Client,
+		attachment: &AttachmentOrThumbnail,
+	) -> Result<Download> {
+		let filename = sanitize_filename(&attachment.filename());
+		let path = self.unused_input_path(&filename);
+		fs::write(&path, attachment.download(client).await?)?;
+
+		if let Some(format) = sniff::sniff_file(&path) {
+			let path = self.fix_extension(path, &filename, format)?;
+			let probe_json = probe::probe_json(&path).await;
+			return Ok(Download {
+				path,
+				format: Some(format),
+				probe_json,
+			});
+		}
+
+		let probe_json = probe::probe_json(&path).await;
+		let format = probe_json
+			.as_ref()
+			.ok()
+			.and_then(|json| probe::parse(json).ok())
+			.and_then(|info| FileFormat::from_media_info(&info));
+		let path = match format {
+			Some(format) => self.fix_extension(path, &filename, format)?,
+			None => path,
+		};
+		Ok(Download {
+			path,
+			format,
+			probe_json,
+		})
+	}
+
+	/// Renames the file if its extension isn't one `format` goes by.
+	fn fix_extension(
+		&self,
+		path: PathBuf,
+		filename: &str,
+		format: FileFormat,
+	) -> Result<PathBuf> {
+		let extension = path
+			.extension()
+			.map(|extension| extension.to_string_lossy().to_string())
+			.unwrap_or_default();
+		if format.matches_extension(&extension) {
+			return Ok(path);
+		}
+		let renamed = self.unused_input_path(
+			&change_extension(filename, format.extension()).to_string_lossy(),
+		);
+		fs::rename(&path, &renamed)?;
+		Ok(renamed)
+	}
+
+	fn unused_input_path(&self, filename: &str) -> PathBuf {
+		let mut path = self.input_path(filename);
+		let mut number = 1;
+		while path.exists() {
+			number += 1;
+			path = self.input_path(&format!("{}_{}", number, filename));
+		}
+		path
+	}
+}
+
+/// An input from [`Workspace::download`] and what it turned out to be.
+#[derive(Debug)]
+pub struct Download {
+	pub path: PathBuf,
+	/// What the file really is, or `None` when neither the magic bytes nor
+	/// ffprobe knew.
+	pub format: Option<FileFormat>,
+	/// ffprobe's JSON for the file, or why it couldn't read it.
+	pub probe_json: Result<Vec<u8>>,
+}
+impl Download {
+	/// What ffprobe found in the file.
+	pub fn info(&self) -> Result<MediaInfo> {
+		match &self.probe_json {
+			Ok(json) => probe::parse(json),
+			Err(e) => Err(anyhow!("{:#}", e)),
+		}
+	}
+}
+
+/// Makes a name from Discord or a URL safe to use as a file name.
+///
+/// Directories are dropped, characters that aren't allowed on common
+/// filesystems become `_`, leading dots and dashes are removed so the name
+/// can't be hidden or read as a flag, and long names are shortened while
+/// keeping the extension.
+pub fn sanitize_filename(name: &str) -> String {
+	let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+	let name = name
+		.chars()
+		.map(|c| match c {
+			'<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+			c if c.is_control() => '_',
+			c => c,
+		})
+		.collect::<String>();
+	let name = name
+		.trim_start_matches(['.', '-', ' '])
+		.trim_end_matches(['.', ' ']);
+	if name.is_empty() {
+		return "file".to_string();
+	}
+	if name.len() <= MAX_FILENAME_LENGTH {
+		return name.to_string();
+	}
+
+	let extension = Path::new(name)
+		.extension()
+		.and_then(|extension| extension.to_str())
+		.filter(|extension| extension.len() < 16)
+		.map(|extension| format!(".{}", extension))
+		.unwrap_or_default();
+	let mut stem_length = MAX_FILENAME_LENGTH - extension.len();
+	while !name.is_char_boundary(stem_length) {
+		stem_length -= 1;
+	}
+	format!("{}{}", &name[..stem_length], extension)
+}